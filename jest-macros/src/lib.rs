@@ -0,0 +1,193 @@
+//! Derive macros for [jest](https://docs.rs/jest). These are re-exported by jest itself,
+//! so use them from there rather than depending on this crate directly.
+#![warn(missing_docs)]
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, spanned::Spanned, Data, DeriveInput, Index, LitStr, Path};
+
+/// Derives `Bundle` for a struct, treating every field as a component.
+#[proc_macro_derive(Bundle)]
+pub fn derive_bundle(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let Data::Struct(data) = &input.data else {
+        return syn::Error::new(input.span(), "`Bundle` can only be derived for structs")
+            .to_compile_error()
+            .into();
+    };
+
+    let name = &input.ident;
+    let types: Vec<_> = data.fields.iter().map(|field| &field.ty).collect();
+    let members = data
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => quote!(#ident),
+            None => {
+                let index = Index::from(i);
+                quote!(#index)
+            }
+        });
+
+    let mut generics = input.generics.clone();
+    let where_clause = generics.make_where_clause();
+    for ty in &types {
+        where_clause
+            .predicates
+            .push(syn::parse_quote!(#ty: ::jest::entities::component::Component));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics ::jest::entities::bundle::Bundle for #name #ty_generics #where_clause {
+            fn component_types() -> ::std::vec::Vec<(::std::any::TypeId, &'static str)> {
+                ::std::vec![#((
+                    ::std::any::TypeId::of::<#types>(),
+                    ::std::any::type_name::<#types>(),
+                )),*]
+            }
+
+            fn take_components(
+                self,
+                f: &mut dyn ::std::ops::FnMut(
+                    ::std::any::TypeId,
+                    ::std::boxed::Box<dyn ::jest::entities::component::Component>,
+                ),
+            ) {
+                #(f(
+                    ::std::any::TypeId::of::<#types>(),
+                    ::std::boxed::Box::new(self.#members),
+                );)*
+            }
+        }
+    }
+    .into()
+}
+
+/// Derives `ComponentOptions` for a type, configured with a `#[component(...)]` attribute.
+/// Unless the type is generic, its options are also submitted to jest so that every world
+/// applies them.
+#[proc_macro_derive(Component, attributes(component))]
+pub fn derive_component(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let mut on_add: Option<Path> = None;
+    let mut on_remove: Option<Path> = None;
+    let mut storage = None;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("component"))
+    {
+        let result = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("on_add") {
+                on_add = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("on_remove") {
+                on_remove = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("storage") {
+                let value: LitStr = meta.value()?.parse()?;
+                storage = Some(match value.value().as_str() {
+                    "table" => quote!(Table),
+                    "sparse" => quote!(SparseSet),
+                    _ => return Err(meta.error("expected `\"table\"` or `\"sparse\"`")),
+                });
+            } else {
+                return Err(meta.error("unknown component option"));
+            }
+            Ok(())
+        });
+        if let Err(err) = result {
+            return err.to_compile_error().into();
+        }
+    }
+
+    let on_add = on_add.map(|path| {
+        quote! {
+            fn on_add(entity: &mut ::jest::entities::Entity) {
+                #path(entity)
+            }
+        }
+    });
+    let on_remove = on_remove.map(|path| {
+        quote! {
+            fn on_remove(entity: &mut ::jest::entities::Entity) {
+                #path(entity)
+            }
+        }
+    });
+
+    let storage = storage.map(|variant| {
+        quote! {
+            const STORAGE: ::jest::entities::component::StorageType =
+                ::jest::entities::component::StorageType::#variant;
+        }
+    });
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    // the options of each instantiation of a generic type can't be submitted up front
+    let submit = input.generics.params.is_empty().then(|| {
+        quote! {
+            ::jest::entities::component::inventory::submit! {
+                ::jest::entities::component::DerivedOptions::of::<#name>()
+            }
+        }
+    });
+    quote! {
+        impl #impl_generics ::jest::entities::component::ComponentOptions for #name #ty_generics #where_clause {
+            #storage
+            #on_add
+            #on_remove
+        }
+        #submit
+    }
+    .into()
+}
+
+/// Derives `Resource` for a type, configured with a `#[resource(...)]` attribute.
+#[proc_macro_derive(Resource, attributes(resource))]
+pub fn derive_resource(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let mut name: Option<LitStr> = None;
+    let mut default = false;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("resource"))
+    {
+        let result = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("default") {
+                default = true;
+            } else {
+                return Err(meta.error("unknown resource option"));
+            }
+            Ok(())
+        });
+        if let Err(err) = result {
+            return err.to_compile_error().into();
+        }
+    }
+
+    let ident = &input.ident;
+    let name = name.unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+    let initial = default.then(|| {
+        quote! {
+            fn initial() -> ::std::option::Option<Self> {
+                ::std::option::Option::Some(::std::default::Default::default())
+            }
+        }
+    });
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    quote! {
+        impl #impl_generics ::jest::resources::Resource for #ident #ty_generics #where_clause {
+            const NAME: &'static str = #name;
+            #initial
+        }
+    }
+    .into()
+}
@@ -0,0 +1,278 @@
+use std::{
+    any::{Any, TypeId},
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    ptr,
+    sync::{Mutex, OnceLock},
+};
+
+use crate::entities::{component::Component, EntityId};
+
+/// The number of rows in the first bucket of a column. Every following bucket is twice
+/// as large as the one before it.
+const BASE: usize = 64;
+/// The number of buckets in a column, enough for far more rows than fit in memory.
+const BUCKETS: usize = 40;
+
+/// Returns the bucket containing `row`, and the index of `row` within it.
+fn locate(row: usize) -> (usize, usize) {
+    let bucket = (usize::BITS - 1 - (row / BASE + 1).leading_zeros()) as usize;
+    (bucket, row - BASE * ((1 << bucket) - 1))
+}
+
+/// The bookkeeping stored alongside every component.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ComponentTicks {
+    /// The version of the component within its entity (see `Entity::version`).
+    pub version: u64,
+    /// The world tick at which the component was added (see `World::change_tick`).
+    pub added: u64,
+    /// The world tick at which the component was last changed.
+    pub changed: u64,
+}
+
+/// A component in a column, along with its ticks.
+struct Slot<T> {
+    value: UnsafeCell<MaybeUninit<T>>,
+    ticks: UnsafeCell<ComponentTicks>,
+}
+
+/// The components of one type stored in an archetype, one per row.
+///
+/// Rows are stored in buckets that are allocated as the column grows and never move
+/// afterwards, so a row can be accessed while other rows are being added, without
+/// locking the whole column. Whether a row is occupied is tracked by the archetype.
+pub(crate) struct TypedColumn<T> {
+    buckets: [OnceLock<Box<[Slot<T>]>>; BUCKETS],
+}
+impl<T> TypedColumn<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| OnceLock::new()),
+        }
+    }
+
+    fn slot(&self, row: usize) -> &Slot<T> {
+        let (bucket, index) = locate(row);
+        &self.buckets[bucket].get().expect("row was never reserved")[index]
+    }
+
+    /// Returns a pointer to the component in `row`, which must have been reserved.
+    pub(crate) fn get(&self, row: usize) -> *mut T {
+        self.slot(row).value.get().cast()
+    }
+
+    /// Returns a pointer to the ticks of the component in `row`, which must have been
+    /// reserved.
+    pub(crate) fn ticks(&self, row: usize) -> *mut ComponentTicks {
+        self.slot(row).ticks.get()
+    }
+}
+// SAFETY: a column only hands out pointers, and the archetype ensures every row is only
+// accessed by whoever holds the lock on the entity stored in it
+unsafe impl<T: Send> Send for TypedColumn<T> {}
+unsafe impl<T: Send + Sync> Sync for TypedColumn<T> {}
+
+/// A [`TypedColumn`] with its component type erased.
+///
+/// The unsafe methods require `row` to be reserved, and to be occupied (or vacant, for
+/// [`write`](Column::write)) as described, with the caller having exclusive access to it.
+pub trait Column: Send + Sync {
+    /// Returns the column as [`Any`], so it can be downcast to a [`TypedColumn`].
+    fn as_any(&self) -> &dyn Any;
+
+    /// Creates a new, empty column for the same component type.
+    fn empty(&self) -> Box<dyn Column>;
+
+    /// Makes sure the bucket containing `row` is allocated.
+    fn reserve(&self, row: usize);
+
+    /// Moves `component` into the vacant `row`.
+    ///
+    /// # Safety
+    /// See [`Column`]. `component` must be of the column's component type.
+    unsafe fn write(&self, row: usize, component: Box<dyn Component>, ticks: ComponentTicks);
+
+    /// Moves the component out of the occupied `row`, leaving it vacant.
+    ///
+    /// # Safety
+    /// See [`Column`].
+    unsafe fn take(&self, row: usize) -> (Box<dyn Component>, ComponentTicks);
+
+    /// Moves the component out of the occupied `row` into the vacant `to_row` of `to`.
+    ///
+    /// # Safety
+    /// See [`Column`]. `to` must be a column of the same component type.
+    unsafe fn move_to(&self, row: usize, to: &dyn Column, to_row: usize);
+
+    /// Drops the component in the occupied `row`, leaving it vacant.
+    ///
+    /// # Safety
+    /// See [`Column`].
+    unsafe fn drop_row(&self, row: usize);
+
+    /// Returns the component in the occupied `row`.
+    ///
+    /// # Safety
+    /// See [`Column`]. The component must not be modified while the reference lives.
+    unsafe fn get_dyn(&self, row: usize) -> &dyn Component;
+
+    /// Returns a pointer to the ticks of the component in `row`.
+    fn ticks_ptr(&self, row: usize) -> *mut ComponentTicks;
+}
+impl dyn Column + '_ {
+    /// Downcasts the column to the [`TypedColumn`] of `T`, which must be its component type.
+    pub(crate) fn typed<T: Component>(&self) -> &TypedColumn<T> {
+        self.as_any().downcast_ref().unwrap()
+    }
+}
+impl<T: Component> Column for TypedColumn<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn empty(&self) -> Box<dyn Column> {
+        Box::new(TypedColumn::<T>::new())
+    }
+
+    fn reserve(&self, row: usize) {
+        let (bucket, _) = locate(row);
+        self.buckets[bucket].get_or_init(|| {
+            (0..BASE << bucket)
+                .map(|_| Slot {
+                    value: UnsafeCell::new(MaybeUninit::uninit()),
+                    ticks: UnsafeCell::default(),
+                })
+                .collect()
+        });
+    }
+
+    unsafe fn write(&self, row: usize, component: Box<dyn Component>, ticks: ComponentTicks) {
+        let component: Box<dyn Any> = component;
+        self.get(row).write(*component.downcast::<T>().unwrap());
+        *self.ticks(row) = ticks;
+    }
+
+    unsafe fn take(&self, row: usize) -> (Box<dyn Component>, ComponentTicks) {
+        (Box::new(self.get(row).read()), *self.ticks(row))
+    }
+
+    unsafe fn move_to(&self, row: usize, to: &dyn Column, to_row: usize) {
+        let to = to.as_any().downcast_ref::<TypedColumn<T>>().unwrap();
+        ptr::copy_nonoverlapping(self.get(row), to.get(to_row), 1);
+        *to.ticks(to_row) = *self.ticks(row);
+    }
+
+    unsafe fn drop_row(&self, row: usize) {
+        self.get(row).drop_in_place();
+    }
+
+    unsafe fn get_dyn(&self, row: usize) -> &dyn Component {
+        &*self.get(row)
+    }
+
+    fn ticks_ptr(&self, row: usize) -> *mut ComponentTicks {
+        self.ticks(row)
+    }
+}
+
+/// Which rows of an archetype are occupied, and by which entities.
+#[derive(Default)]
+struct Rows {
+    entities: Vec<Option<EntityId>>,
+    free: Vec<usize>,
+}
+
+/// The storage for every entity with exactly a given set of component types: one
+/// [`Column`] per type, and one row per entity.
+pub(crate) struct Archetype {
+    // sorted, so that the signature is unique and columns can be binary searched
+    types: Vec<TypeId>,
+    columns: Vec<Box<dyn Column>>,
+    rows: Mutex<Rows>,
+}
+impl Archetype {
+    /// Creates an archetype from its columns, which must be sorted by type ID.
+    pub(crate) fn new(types: Vec<TypeId>, columns: Vec<Box<dyn Column>>) -> Self {
+        debug_assert!(types.windows(2).all(|pair| pair[0] < pair[1]));
+        debug_assert_eq!(types.len(), columns.len());
+        Self {
+            types,
+            columns,
+            rows: Mutex::default(),
+        }
+    }
+
+    /// Returns the component types stored in this archetype, sorted by type ID.
+    pub(crate) fn types(&self) -> &[TypeId] {
+        &self.types
+    }
+
+    /// Returns the columns of this archetype, in the same order as [`types`](Archetype::types).
+    pub(crate) fn columns(&self) -> &[Box<dyn Column>] {
+        &self.columns
+    }
+
+    /// Returns the column storing components with type ID `id`.
+    pub(crate) fn column(&self, id: TypeId) -> Option<&dyn Column> {
+        let index = self.types.binary_search(&id).ok()?;
+        Some(&*self.columns[index])
+    }
+
+    /// Returns whether this archetype stores every type in `ids`.
+    pub(crate) fn contains_all(&self, ids: &[TypeId]) -> bool {
+        ids.iter().all(|id| self.types.binary_search(id).is_ok())
+    }
+
+    /// Finds a vacant row for `entity` and reserves it in every column. The caller must
+    /// then write a component to every column.
+    pub(crate) fn alloc(&self, entity: EntityId) -> usize {
+        let mut rows = self.rows.lock().unwrap();
+        let row = match rows.free.pop() {
+            Some(row) => {
+                rows.entities[row] = Some(entity);
+                row
+            }
+            None => {
+                rows.entities.push(Some(entity));
+                rows.entities.len() - 1
+            }
+        };
+        for column in &self.columns {
+            column.reserve(row);
+        }
+        row
+    }
+
+    /// Marks `row` as vacant. Its components must have been moved out already.
+    pub(crate) fn free(&self, row: usize) {
+        let mut rows = self.rows.lock().unwrap();
+        rows.entities[row] = None;
+        rows.free.push(row);
+    }
+
+    /// Returns the IDs of the entities currently stored in this archetype.
+    pub(crate) fn entities(&self) -> Vec<EntityId> {
+        self.rows
+            .lock()
+            .unwrap()
+            .entities
+            .iter()
+            .flatten()
+            .copied()
+            .collect()
+    }
+}
+impl Drop for Archetype {
+    fn drop(&mut self) {
+        let rows = self.rows.get_mut().unwrap();
+        for (row, entity) in rows.entities.iter().enumerate() {
+            if entity.is_some() {
+                for column in &self.columns {
+                    // SAFETY: the row is occupied, and nothing else can access it anymore
+                    unsafe { column.drop_row(row) };
+                }
+            }
+        }
+    }
+}
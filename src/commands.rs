@@ -0,0 +1,216 @@
+use std::{
+    any::{type_name, TypeId},
+    future::Future,
+    sync::Arc,
+};
+
+use crate::{
+    entities::{builder::EntityBuilder, component::Component, fetch::Access, Entity, EntityId},
+    hierarchy,
+    systems::SystemParam,
+    transaction::Transaction,
+    world::World,
+};
+
+/// A structural change recorded by [`Commands`].
+pub(crate) enum Command {
+    Spawn(EntityBuilder),
+    Despawn(EntityId),
+    DespawnRecursive(EntityId),
+    AddChild { parent: EntityId, child: EntityId },
+    RemoveChild { parent: EntityId, child: EntityId },
+    Modify(EntityId, Box<dyn FnOnce(&mut Entity) + Send>),
+    Commit(Transaction),
+}
+impl Command {
+    pub(crate) async fn apply(self, world: &Arc<World>) {
+        match self {
+            Command::Spawn(builder) => {
+                builder.build(world).await;
+            }
+            Command::Despawn(id) => {
+                world.remove(id).await;
+            }
+            Command::DespawnRecursive(id) => {
+                world.despawn_recursive(id).await;
+            }
+            Command::AddChild { parent, child } => {
+                if let Some(mut parent) = world.get_mut(parent).await {
+                    let _ = parent.add_child(child).await;
+                }
+            }
+            Command::RemoveChild { parent, child } => {
+                if let Some(mut parent) = world.get_mut(parent).await {
+                    parent.remove_child(child).await;
+                }
+            }
+            Command::Modify(id, f) => {
+                if let Some(mut entity) = world.get_mut(id).await {
+                    f(&mut entity);
+                }
+            }
+            Command::Commit(transaction) => {
+                let result = world.commit(transaction).await;
+                #[cfg(feature = "tracing")]
+                if let Err(error) = result {
+                    tracing::debug!(%error, "transaction rolled back");
+                }
+                #[cfg(not(feature = "tracing"))]
+                let _ = result;
+            }
+        }
+    }
+}
+
+/// A buffer of structural changes to a world, such as spawning and despawning entities,
+/// that are applied later by [`World::apply_commands`].
+///
+/// These changes need to lock the entire world, so making them directly while iterating
+/// a [`Query`](crate::query::Query) would deadlock. Record them here instead: the commands
+/// are queued on the world when the buffer is dropped, and a [`Schedule`](crate::systems::schedule::Schedule)
+/// applies them once all of its systems have finished. Commands from the same buffer are
+/// applied in the order they were recorded. Ones targeting an entity that no longer exists
+/// are skipped, and so is adding a component the entity already has.
+/// ```rust
+/// use jest::{
+///     world::World,
+///     entities::builder::EntityBuilder,
+///     commands::Commands,
+///     query::Query,
+///     systems::schedule::Schedule,
+/// };
+///
+/// struct Health(u32);
+/// struct Corpse;
+///
+/// async fn die(mut query: Query<&Health>, mut commands: Commands) {
+///     let mut iter = query.iter().await;
+///     while let Some((id, health)) = iter.next().await {
+///         if health.0 == 0 {
+///             commands.despawn(id);
+///             let mut corpse = EntityBuilder::new();
+///             corpse.add(Corpse).unwrap();
+///             commands.spawn(corpse);
+///         }
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     let mut builder = EntityBuilder::new();
+///     builder.add(Health(0)).unwrap();
+///     let entity_id = builder.build(&world).await;
+///
+///     let mut schedule = Schedule::new();
+///     schedule.add_system(die);
+///     schedule.run(&world).await;
+///
+///     assert!(world.get(entity_id).await.is_none());
+///     assert_eq!(world.find(|entity| entity.get::<Corpse>().is_some()).await.len(), 1);
+/// }
+/// ```
+pub struct Commands {
+    world: Arc<World>,
+    queue: Vec<Command>,
+}
+impl Commands {
+    /// Creates an empty buffer whose commands will be queued on `world`.
+    pub fn new(world: &Arc<World>) -> Self {
+        Self {
+            world: world.clone(),
+            queue: Vec::new(),
+        }
+    }
+
+    /// Builds the entity and adds it to the world.
+    pub fn spawn(&mut self, builder: EntityBuilder) -> &mut Self {
+        self.queue.push(Command::Spawn(builder));
+        self
+    }
+
+    /// Removes the entity specified by `id` from the world.
+    pub fn despawn(&mut self, id: EntityId) -> &mut Self {
+        self.queue.push(Command::Despawn(id));
+        self
+    }
+
+    /// Removes the entity specified by `id` from the world, along with all of its
+    /// descendants.
+    pub fn despawn_recursive(&mut self, id: EntityId) -> &mut Self {
+        self.queue.push(Command::DespawnRecursive(id));
+        self
+    }
+
+    /// Makes the entity specified by `child` a child of the one specified by `parent`,
+    /// see [`EntityMut::add_child`](crate::entities::EntityMut::add_child). Nothing
+    /// happens if that would make an entity a descendant of itself.
+    pub fn add_child(&mut self, parent: EntityId, child: EntityId) -> &mut Self {
+        self.queue.push(Command::AddChild { parent, child });
+        self
+    }
+
+    /// Detaches the entity specified by `child` from the one specified by `parent`, if it
+    /// is one of its children.
+    pub fn remove_child(&mut self, parent: EntityId, child: EntityId) -> &mut Self {
+        self.queue.push(Command::RemoveChild { parent, child });
+        self
+    }
+
+    /// Adds a component of type `T` to the entity specified by `id`.
+    ///
+    /// # Panics
+    /// Panics if `T` is [`Parent`](crate::hierarchy::Parent) or
+    /// [`Children`](crate::hierarchy::Children), see [`add_child`](Commands::add_child).
+    pub fn add<T: Component>(&mut self, id: EntityId, component: T) -> &mut Self {
+        hierarchy::assert_not_link(TypeId::of::<T>(), type_name::<T>());
+        self.queue.push(Command::Modify(
+            id,
+            Box::new(move |entity| {
+                let _ = entity.add(component);
+            }),
+        ));
+        self
+    }
+
+    /// Removes the component of type `T` from the entity specified by `id`.
+    pub fn remove<T: Component>(&mut self, id: EntityId) -> &mut Self {
+        self.queue.push(Command::Modify(
+            id,
+            Box::new(|entity| {
+                entity.remove::<T>();
+            }),
+        ));
+        self
+    }
+
+    /// Commits `transaction` with [`World::commit`]. If it fails, it is rolled back like
+    /// any other, and the following commands are still applied.
+    pub fn commit(&mut self, transaction: Transaction) -> &mut Self {
+        self.queue.push(Command::Commit(transaction));
+        self
+    }
+}
+
+/// Queues the recorded commands on the world.
+impl Drop for Commands {
+    fn drop(&mut self) {
+        if !self.queue.is_empty() {
+            self.world.commands.lock().unwrap().append(&mut self.queue);
+        }
+    }
+}
+
+/// Commands can be taken by a system. They don't access any components while it runs.
+impl SystemParam for Commands {
+    fn access(_access: &mut Vec<Access>) {}
+
+    fn fetch(
+        world: &Arc<World>,
+        _last_run: u64,
+        _this_run: u64,
+    ) -> impl Future<Output = Self> + Send {
+        let commands = Commands::new(world);
+        async { commands }
+    }
+}
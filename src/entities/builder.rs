@@ -1,20 +1,38 @@
 use std::{
-    any::{Any, TypeId},
+    any::{type_name, Any, TypeId},
     collections::{hash_map::Entry, HashMap},
     sync::Arc,
 };
 
-use crate::world::World;
+use crate::{hierarchy, world::World};
 
 use super::{
-    errors::{self, AlreadyExists},
+    bundle::Bundle,
+    component::Component,
+    errors::{self, AlreadyExists, NotRepeatable},
     Entity, EntityId,
 };
 
+/// A component waiting to be added to a built entity.
+enum PendingComponent {
+    /// Added by value, so it can only be given to one entity.
+    Value(Box<dyn Component>),
+    /// Produces a fresh value for every entity built.
+    Factory(Box<dyn FnMut() -> Box<dyn Component> + Send>),
+}
+impl PendingComponent {
+    fn into_inner(self) -> Box<dyn Component> {
+        match self {
+            PendingComponent::Value(component) => component,
+            PendingComponent::Factory(mut factory) => factory(),
+        }
+    }
+}
+
 /// A builder for creating entities and adding them to a world.
 #[derive(Default)]
 pub struct EntityBuilder {
-    components: HashMap<TypeId, Box<dyn Any + Send>>,
+    components: HashMap<TypeId, PendingComponent>,
 }
 impl EntityBuilder {
     /// Creates a new entity builder.
@@ -22,27 +40,271 @@ impl EntityBuilder {
         Self::default()
     }
 
-    /// Adds a component of type `T` to the entity, returning [`AlreadyExists`](errors::AlreadyExists) if
-    /// a component of the same type already exists.. `T` must satisfy
-    /// [`'static`](https://doc.rust-lang.org/rust-by-example/scope/lifetime/static_lifetime.html#trait-bound)
-    /// and [`Send`].
-    pub fn add<T: Any + Send>(&mut self, component: T) -> Result<&mut Self, AlreadyExists> {
+    /// Creates a builder containing a copy of every component of `entity` whose type has
+    /// been registered with [`World::register_clone`]. Other components are skipped.
+    /// The copies are cloned again for every entity built, so the builder can also be used
+    /// with [`build_many`](EntityBuilder::build_many).
+    /// ```rust
+    /// use jest::{world::World, entities::builder::EntityBuilder};
+    ///
+    /// #[derive(Clone)]
+    /// struct Health(u32);
+    /// struct Unique;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///     world.register_clone::<Health>();
+    ///
+    ///     let mut builder = EntityBuilder::new();
+    ///     builder.add(Health(100)).unwrap().add(Unique).unwrap();
+    ///     let template = builder.build(&world).await;
+    ///
+    ///     let builder = EntityBuilder::from_entity(&world.get(template).await.unwrap());
+    ///     let copy = builder.build(&world).await;
+    ///
+    ///     let copy = world.get(copy).await.unwrap();
+    ///     assert_eq!(copy.get::<Health>().unwrap().0, 100);
+    ///     assert!(copy.get::<Unique>().is_none());
+    /// }
+    /// ```
+    pub fn from_entity(entity: &Entity) -> Self {
+        let cloners = entity.world.cloners.read().unwrap();
+        let components = entity
+            .components()
+            .into_iter()
+            .filter_map(|(id, component)| {
+                let clone = *cloners.get(&id)?;
+                let template = clone(component);
+                let factory = PendingComponent::Factory(Box::new(move || clone(&*template)));
+                Some((id, factory))
+            })
+            .collect();
+        Self { components }
+    }
+
+    fn insert<T: Any>(&mut self, component: PendingComponent) -> Result<&mut Self, AlreadyExists> {
+        hierarchy::assert_not_link(TypeId::of::<T>(), type_name::<T>());
         match self.components.entry(TypeId::of::<T>()) {
-            Entry::Occupied(_) => Err(errors::AlreadyExists),
+            Entry::Occupied(_) => Err(errors::AlreadyExists {
+                component: type_name::<T>(),
+                entity: None,
+            }),
             Entry::Vacant(entry) => {
-                entry.insert(Box::new(component));
+                entry.insert(component);
                 Ok(self)
             }
         }
     }
 
+    /// Adds a component of type `T` to the entity, returning [`AlreadyExists`](errors::AlreadyExists) if
+    /// a component of the same type already exists.. Any type satisfying
+    /// [`'static`](https://doc.rust-lang.org/rust-by-example/scope/lifetime/static_lifetime.html#trait-bound),
+    /// [`Send`] and [`Sync`] is a [`Component`](trait@Component).
+    ///
+    /// # Panics
+    /// Panics if `T` is [`Parent`](crate::hierarchy::Parent) or
+    /// [`Children`](crate::hierarchy::Children); the hierarchy is built with
+    /// [`EntityMut::add_child`](crate::entities::EntityMut::add_child) once the entity is
+    /// spawned. The same goes for every other way of adding components to the builder.
+    pub fn add<T: Component>(&mut self, component: T) -> Result<&mut Self, AlreadyExists> {
+        self.insert::<T>(PendingComponent::Value(Box::new(component)))
+    }
+
+    /// Adds the component produced by `f` if `condition` is true, otherwise does nothing.
+    /// `f` is only called when the component is actually added.
+    /// ```rust
+    /// use jest::{world::World, entities::builder::EntityBuilder};
+    ///
+    /// struct Boss;
+    /// struct Health(u32);
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///     let is_boss = true;
+    ///     let armor: Option<u32> = None;
+    ///
+    ///     let mut builder = EntityBuilder::new();
+    ///     builder
+    ///         .add(Health(100))
+    ///         .unwrap()
+    ///         .add_if(is_boss, || Boss)
+    ///         .unwrap()
+    ///         .add_some(armor)
+    ///         .unwrap();
+    ///     let entity_id = builder.build(&world).await;
+    ///
+    ///     let entity = world.get(entity_id).await.unwrap();
+    ///     assert!(entity.get::<Boss>().is_some());
+    ///     assert!(entity.get::<u32>().is_none());
+    /// }
+    /// ```
+    pub fn add_if<T: Component>(
+        &mut self,
+        condition: bool,
+        f: impl FnOnce() -> T,
+    ) -> Result<&mut Self, AlreadyExists> {
+        if condition {
+            self.add(f())
+        } else {
+            Ok(self)
+        }
+    }
+
+    /// Adds the component contained in `component` if it is `Some`, otherwise does nothing.
+    pub fn add_some<T: Component>(
+        &mut self,
+        component: Option<T>,
+    ) -> Result<&mut Self, AlreadyExists> {
+        match component {
+            Some(component) => self.add(component),
+            None => Ok(self),
+        }
+    }
+
+    /// Like [`add`](EntityBuilder::add), but the component is cloned for every entity
+    /// built with [`build_many`](EntityBuilder::build_many).
+    pub fn add_clone<T: Component + Clone>(
+        &mut self,
+        component: T,
+    ) -> Result<&mut Self, AlreadyExists> {
+        self.insert::<T>(PendingComponent::Factory(Box::new(move || {
+            Box::new(component.clone())
+        })))
+    }
+
+    /// Like [`add`](EntityBuilder::add), but the component is produced by calling `f`
+    /// once for every entity built.
+    pub fn add_with<T: Component>(
+        &mut self,
+        mut f: impl FnMut() -> T + Send + 'static,
+    ) -> Result<&mut Self, AlreadyExists> {
+        self.insert::<T>(PendingComponent::Factory(Box::new(move || Box::new(f()))))
+    }
+
+    /// Adds every component of `bundle` to the entity, returning [`AlreadyExists`](errors::AlreadyExists)
+    /// if any of them is already present or the bundle contains the same type twice.
+    /// Nothing is added unless the whole bundle can be.
+    /// ```rust
+    /// use jest::{world::World, entities::builder::EntityBuilder};
+    ///
+    /// struct Position(f32, f32);
+    /// struct Velocity(f32, f32);
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///
+    ///     let mut builder = EntityBuilder::new();
+    ///     builder
+    ///         .add_bundle((Position(0.0, 0.0), Velocity(1.0, 0.0)))
+    ///         .unwrap();
+    ///     assert!(builder.add(Position(1.0, 1.0)).is_err());
+    ///     let entity_id = builder.build(&world).await;
+    ///
+    ///     let entity = world.get(entity_id).await.unwrap();
+    ///     assert!(entity.get::<Velocity>().is_some());
+    /// }
+    /// ```
+    pub fn add_bundle<B: Bundle>(&mut self, bundle: B) -> Result<&mut Self, AlreadyExists> {
+        let types = B::component_types();
+        for (i, (id, name)) in types.iter().enumerate() {
+            hierarchy::assert_not_link(*id, name);
+            if self.components.contains_key(id) || types[..i].iter().any(|(other, _)| other == id) {
+                return Err(errors::AlreadyExists {
+                    component: name,
+                    entity: None,
+                });
+            }
+        }
+        bundle.take_components(&mut |id, component| {
+            self.components
+                .insert(id, PendingComponent::Value(component));
+        });
+        Ok(self)
+    }
+
     /// Builds the entity and adds it to the world, returning its ID.
     pub async fn build(self, world: &Arc<World>) -> EntityId {
         world
-            .insert(Entity {
-                components: self.components,
-                _world: world.clone(),
-            })
+            .insert(Entity::new(
+                self.components
+                    .into_iter()
+                    .map(|(id, component)| (id, component.into_inner()))
+                    .collect(),
+                world.clone(),
+            ))
             .await
     }
+
+    /// Builds `n` entities and adds them all to the world at once, returning their IDs.
+    /// Every component must have been added with [`add_clone`](EntityBuilder::add_clone)
+    /// or [`add_with`](EntityBuilder::add_with), otherwise [`NotRepeatable`](errors::NotRepeatable)
+    /// is returned and nothing is added.
+    /// ```rust
+    /// use jest::{world::World, entities::builder::EntityBuilder};
+    ///
+    /// #[derive(Clone)]
+    /// struct Tile;
+    /// struct Index(usize);
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///
+    ///     let mut next = 0;
+    ///     let mut builder = EntityBuilder::new();
+    ///     builder.add_clone(Tile).unwrap();
+    ///     builder
+    ///         .add_with(move || {
+    ///             next += 1;
+    ///             Index(next - 1)
+    ///         })
+    ///         .unwrap();
+    ///     let tiles = builder.build_many(&world, 16).await.unwrap();
+    ///
+    ///     assert_eq!(tiles.len(), 16);
+    ///     let last = world.get(tiles[15]).await.unwrap();
+    ///     assert_eq!(last.get::<Index>().unwrap().0, 15);
+    /// }
+    /// ```
+    pub async fn build_many(
+        self,
+        world: &Arc<World>,
+        n: usize,
+    ) -> Result<Vec<EntityId>, NotRepeatable> {
+        let mut factories = Vec::with_capacity(self.components.len());
+        for (id, component) in self.components {
+            match component {
+                PendingComponent::Value(_) => return Err(errors::NotRepeatable),
+                PendingComponent::Factory(factory) => factories.push((id, factory)),
+            }
+        }
+        let entities = (0..n)
+            .map(|_| {
+                Entity::new(
+                    factories
+                        .iter_mut()
+                        .map(|(id, factory)| (*id, factory()))
+                        .collect(),
+                    world.clone(),
+                )
+            })
+            .collect();
+        Ok(world.insert_many(entities).await)
+    }
+}
+
+/// Turns an entity back into a builder containing all of its components,
+/// e.g. to move it into another world.
+impl From<Entity> for EntityBuilder {
+    fn from(mut entity: Entity) -> Self {
+        Self {
+            components: entity
+                .drain()
+                .map(|(id, component)| (id, PendingComponent::Value(component)))
+                .collect(),
+        }
+    }
 }
@@ -0,0 +1,83 @@
+use std::any::{type_name, TypeId};
+
+use super::component::Component;
+
+/// Derives [`Bundle`](trait@Bundle) for a struct whose fields are all components.
+pub use jest_macros::Bundle;
+
+/// A group of components that can be added to an entity in one go.
+///
+/// `Bundle` is implemented for tuples of up to 12 components, and can be
+/// derived for structs whose fields are all components:
+/// ```rust
+/// use jest::{world::World, entities::{builder::EntityBuilder, bundle::Bundle}};
+///
+/// struct Position(f32, f32);
+/// struct Velocity(f32, f32);
+/// struct Health(u32);
+///
+/// #[derive(Bundle)]
+/// struct PlayerBundle {
+///     position: Position,
+///     velocity: Velocity,
+///     health: Health,
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     let mut builder = EntityBuilder::new();
+///     builder
+///         .add_bundle(PlayerBundle {
+///             position: Position(0.0, 0.0),
+///             velocity: Velocity(1.0, 0.0),
+///             health: Health(100),
+///         })
+///         .unwrap();
+///     let player = builder.build(&world).await;
+///
+///     assert_eq!(world.get(player).await.unwrap().get::<Health>().unwrap().0, 100);
+/// }
+/// ```
+///
+/// Nested tuples aren't flattened. Any `Send + Sync + 'static` type is a component, so
+/// adding `((A, B), C)` stores the inner tuple as a single `(A, B)` component, next to
+/// a `C`.
+pub trait Bundle: Send + 'static {
+    /// Returns the type ID and type name of every component in this bundle, in the order
+    /// [`take_components`](Bundle::take_components) yields them.
+    fn component_types() -> Vec<(TypeId, &'static str)>;
+
+    /// Moves each component out of the bundle, passing it to `f` along with its type ID.
+    fn take_components(self, f: &mut dyn FnMut(TypeId, Box<dyn Component>));
+}
+
+macro_rules! impl_bundle {
+    ($($name:ident),*) => {
+        impl<$($name: Component),*> Bundle for ($($name,)*) {
+            fn component_types() -> Vec<(TypeId, &'static str)> {
+                vec![$((TypeId::of::<$name>(), type_name::<$name>())),*]
+            }
+
+            #[allow(non_snake_case, unused_variables)]
+            fn take_components(self, f: &mut dyn FnMut(TypeId, Box<dyn Component>)) {
+                let ($($name,)*) = self;
+                $(f(TypeId::of::<$name>(), Box::new($name));)*
+            }
+        }
+    };
+}
+
+impl_bundle!();
+impl_bundle!(A);
+impl_bundle!(A, B);
+impl_bundle!(A, B, C);
+impl_bundle!(A, B, C, D);
+impl_bundle!(A, B, C, D, E);
+impl_bundle!(A, B, C, D, E, F);
+impl_bundle!(A, B, C, D, E, F, G);
+impl_bundle!(A, B, C, D, E, F, G, H);
+impl_bundle!(A, B, C, D, E, F, G, H, I);
+impl_bundle!(A, B, C, D, E, F, G, H, I, J);
+impl_bundle!(A, B, C, D, E, F, G, H, I, J, K);
+impl_bundle!(A, B, C, D, E, F, G, H, I, J, K, L);
@@ -0,0 +1,165 @@
+use std::any::{type_name, Any, TypeId};
+
+use crate::archetype::{Column, TypedColumn};
+
+use super::Entity;
+
+/// Derives [`ComponentOptions`] for a type, configured with `#[component(...)]` attributes:
+/// - `on_add = path`: a `fn(&mut Entity)` called right after a component of this type
+///   is added to an entity, including when the entity is spawned.
+/// - `on_remove = path`: a `fn(&mut Entity)` called right before a component of this type
+///   is removed from an entity, including when the entity is despawned.
+/// - `storage = "sparse"` or `storage = "table"`: where components of this type are
+///   stored, see [`StorageType`].
+///
+/// The derived options apply in every world without any registration. The one exception
+/// is generic types, whose options only take effect once each of their instantiations is
+/// registered with [`World::register_component`](crate::world::World::register_component).
+pub use jest_macros::Component;
+
+#[doc(hidden)]
+pub use inventory;
+
+/// Data that can be stored on an entity. This is implemented for every type satisfying
+/// [`'static`](https://doc.rust-lang.org/rust-by-example/scope/lifetime/static_lifetime.html#trait-bound),
+/// [`Send`] and [`Sync`], so it never needs to be implemented by hand. `Sync` is needed
+/// because systems running in parallel can read the same component at once.
+pub trait Component: Any + Send + Sync {
+    /// Creates an empty column for storing components of this type in an archetype.
+    #[doc(hidden)]
+    fn new_column(&self) -> Box<dyn Column>;
+}
+impl<T: Any + Send + Sync> Component for T {
+    fn new_column(&self) -> Box<dyn Column> {
+        Box::new(TypedColumn::<T>::new())
+    }
+}
+
+/// Per-type configuration of a component, usually derived with
+/// [`#[derive(Component)]`](macro@Component), which makes every world apply it. Options
+/// implemented by hand, or derived for a generic type, only take effect once the type is
+/// registered with [`World::register_component`](crate::world::World::register_component).
+/// ```rust
+/// use jest::{world::World, entities::{builder::EntityBuilder, component::Component, Entity}};
+///
+/// struct Alive;
+///
+/// #[derive(Component)]
+/// #[component(on_add = mark_alive, on_remove = mark_dead)]
+/// struct Health(u32);
+///
+/// fn mark_alive(entity: &mut Entity) {
+///     entity.replace(Alive);
+/// }
+///
+/// fn mark_dead(entity: &mut Entity) {
+///     entity.remove::<Alive>();
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///
+///     let mut builder = EntityBuilder::new();
+///     builder.add(Health(100)).unwrap();
+///     let entity_id = builder.build(&world).await;
+///
+///     let mut entity = world.get_mut(entity_id).await.unwrap();
+///     assert!(entity.get::<Alive>().is_some());
+///     entity.remove::<Health>();
+///     assert!(entity.get::<Alive>().is_none());
+/// }
+/// ```
+pub trait ComponentOptions: Component {
+    /// Where components of this type are stored.
+    const STORAGE: StorageType = StorageType::Table;
+
+    /// Called right after a component of this type is added to `entity`.
+    fn on_add(_entity: &mut Entity) {}
+
+    /// Called right before a component of this type is removed from `entity`.
+    fn on_remove(_entity: &mut Entity) {}
+}
+
+/// Where the components of a type are stored within a world.
+///
+/// Table storage is the fastest to iterate, but adding or removing a component moves
+/// all of the entity's other table components to a different archetype. Sparse set
+/// storage keeps every component of the type in a single set owned by the world, so it
+/// can be added and removed without touching the entity's other components, at the cost
+/// of slower queries. Prefer it for markers that come and go every few frames:
+/// ```rust
+/// use jest::{world::World, entities::{builder::EntityBuilder, component::Component}, query::Query};
+///
+/// struct Health(u32);
+///
+/// #[derive(Component)]
+/// #[component(storage = "sparse")]
+/// struct Stunned;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///
+///     let mut builder = EntityBuilder::new();
+///     builder.add(Health(100)).unwrap();
+///     let entity_id = builder.build(&world).await;
+///
+///     world.get_mut(entity_id).await.unwrap().add(Stunned).unwrap();
+///     let mut query = world.query::<(&Health, &Stunned)>();
+///     let mut iter = query.iter().await;
+///     let (_, (health, _)) = iter.next().await.unwrap();
+///     assert_eq!(health.0, 100);
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StorageType {
+    /// Stored in the archetype matching the entity's set of table components.
+    #[default]
+    Table,
+    /// Stored in a sparse set shared by every entity with a component of this type.
+    SparseSet,
+}
+
+/// The options of a registered component type, with the type erased.
+#[derive(Clone, Copy)]
+pub(crate) struct ComponentInfo {
+    pub(crate) name: &'static str,
+    pub(crate) on_add: fn(&mut Entity),
+    pub(crate) on_remove: fn(&mut Entity),
+    pub(crate) storage: StorageType,
+}
+impl ComponentInfo {
+    pub(crate) fn of<T: ComponentOptions>() -> Self {
+        Self {
+            name: type_name::<T>(),
+            storage: T::STORAGE,
+            on_add: T::on_add,
+            on_remove: T::on_remove,
+        }
+    }
+}
+
+/// The options of a type deriving [`Component`], submitted by the derive so that every
+/// world picks them up.
+#[doc(hidden)]
+pub struct DerivedOptions {
+    type_id: fn() -> TypeId,
+    info: fn() -> ComponentInfo,
+}
+impl DerivedOptions {
+    pub const fn of<T: ComponentOptions>() -> Self {
+        Self {
+            type_id: TypeId::of::<T>,
+            info: ComponentInfo::of::<T>,
+        }
+    }
+}
+inventory::collect!(DerivedOptions);
+
+/// Returns the options of every type deriving [`Component`].
+pub(crate) fn derived_options() -> impl Iterator<Item = (TypeId, ComponentInfo)> {
+    inventory::iter::<DerivedOptions>
+        .into_iter()
+        .map(|options| ((options.type_id)(), (options.info)()))
+}
@@ -0,0 +1,229 @@
+use std::{
+    any::{type_name, TypeId},
+    ops::Deref,
+};
+
+use crate::archetype::ComponentTicks;
+
+use super::{component::Component, Entity};
+
+/// A single access to a component made by a [`Fetch`].
+#[derive(Debug, Clone, Copy)]
+pub struct Access {
+    /// The type ID of the component.
+    pub id: TypeId,
+    /// The type name of the component, for error messages.
+    pub name: &'static str,
+    /// Whether the component is accessed mutably.
+    pub mutable: bool,
+    /// Whether the component is only checked for by a [`QueryFilter`](crate::query::QueryFilter),
+    /// without being borrowed.
+    pub filter: bool,
+}
+
+/// A component reference (`&T`, `&mut T` or [`Ref<T>`]), or a tuple of up to 12 of them,
+/// that can be fetched from an entity with [`Entity::get_many`].
+pub trait Fetch {
+    /// The references produced by this fetch.
+    type Item<'a>;
+
+    /// Pushes every component access made by this fetch onto `access`.
+    fn access(access: &mut Vec<Access>);
+
+    /// Fetches the components from `entity`, returning `None` if any of them is missing.
+    /// `last_run` is the [change tick](crate::world::World::change_tick) changes are
+    /// detected relative to, and `this_run` the tick components borrowed mutably are
+    /// marked as changed at.
+    ///
+    /// # Safety
+    /// `entity` must be valid for `'a`, and the accesses reported by [`access`](Fetch::access)
+    /// must not conflict with each other or with any other live reference to the entity.
+    unsafe fn fetch<'a>(
+        entity: *mut Entity,
+        last_run: u64,
+        this_run: u64,
+    ) -> Option<Self::Item<'a>>;
+}
+
+impl<T: Component> Fetch for &T {
+    type Item<'a> = &'a T;
+
+    fn access(access: &mut Vec<Access>) {
+        access.push(Access {
+            id: TypeId::of::<T>(),
+            name: type_name::<T>(),
+            mutable: false,
+            filter: false,
+        });
+    }
+
+    unsafe fn fetch<'a>(
+        entity: *mut Entity,
+        _last_run: u64,
+        _this_run: u64,
+    ) -> Option<Self::Item<'a>> {
+        Entity::component_ptr::<T>(entity).map(|component| &*component)
+    }
+}
+
+impl<T: Component> Fetch for &mut T {
+    type Item<'a> = &'a mut T;
+
+    fn access(access: &mut Vec<Access>) {
+        access.push(Access {
+            id: TypeId::of::<T>(),
+            name: type_name::<T>(),
+            mutable: true,
+            filter: false,
+        });
+    }
+
+    unsafe fn fetch<'a>(
+        entity: *mut Entity,
+        _last_run: u64,
+        this_run: u64,
+    ) -> Option<Self::Item<'a>> {
+        let component = Entity::component_mut_ptr::<T>(entity)?;
+        (*entity).touch(TypeId::of::<T>(), this_run);
+        Some(&mut *component)
+    }
+}
+
+/// An immutable reference to a component that also tells whether it was added or
+/// changed since the querying system last ran, like the [`Added`](crate::query::Added)
+/// and [`Changed`](crate::query::Changed) filters. This type implements `Deref` for
+/// usage as a normal reference.
+/// ```rust
+/// use jest::{world::World, entities::{builder::EntityBuilder, fetch::Ref}};
+///
+/// struct Health(u32);
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     let mut builder = EntityBuilder::new();
+///     builder.add(Health(100)).unwrap();
+///     let entity_id = builder.build(&world).await;
+///
+///     let mut entity = world.get_mut(entity_id).await.unwrap();
+///     let health = entity.get_many::<Ref<Health>>().unwrap();
+///     assert_eq!(health.0, 100);
+///     assert!(health.is_added() && health.is_changed());
+/// }
+/// ```
+pub struct Ref<'a, T> {
+    value: &'a T,
+    ticks: ComponentTicks,
+    last_run: u64,
+}
+impl<T> Ref<'_, T> {
+    /// Returns whether the component was added since the querying system last ran.
+    pub fn is_added(&self) -> bool {
+        self.ticks.added > self.last_run
+    }
+
+    /// Returns whether the component was added or mutably borrowed since the querying
+    /// system last ran.
+    pub fn is_changed(&self) -> bool {
+        self.ticks.changed > self.last_run
+    }
+}
+/// Get a reference to the underlying component.
+impl<T> Deref for Ref<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<T: Component> Fetch for Ref<'_, T> {
+    type Item<'a> = Ref<'a, T>;
+
+    fn access(access: &mut Vec<Access>) {
+        <&T>::access(access);
+    }
+
+    unsafe fn fetch<'a>(
+        entity: *mut Entity,
+        last_run: u64,
+        _this_run: u64,
+    ) -> Option<Self::Item<'a>> {
+        Some(Ref {
+            value: &*Entity::component_ptr::<T>(entity)?,
+            ticks: (*entity).ticks(TypeId::of::<T>())?,
+            last_run,
+        })
+    }
+}
+
+macro_rules! impl_fetch {
+    ($($name:ident),*) => {
+        impl<$($name: Fetch),*> Fetch for ($($name,)*) {
+            type Item<'a> = ($($name::Item<'a>,)*);
+
+            #[allow(unused_variables)]
+            fn access(access: &mut Vec<Access>) {
+                $($name::access(access);)*
+            }
+
+            #[allow(unused_variables)]
+            unsafe fn fetch<'a>(
+                entity: *mut Entity,
+                last_run: u64,
+                this_run: u64,
+            ) -> Option<Self::Item<'a>> {
+                Some(($($name::fetch(entity, last_run, this_run)?,)*))
+            }
+        }
+    };
+}
+
+impl_fetch!();
+impl_fetch!(A);
+impl_fetch!(A, B);
+impl_fetch!(A, B, C);
+impl_fetch!(A, B, C, D);
+impl_fetch!(A, B, C, D, E);
+impl_fetch!(A, B, C, D, E, F);
+impl_fetch!(A, B, C, D, E, F, G);
+impl_fetch!(A, B, C, D, E, F, G, H);
+impl_fetch!(A, B, C, D, E, F, G, H, I);
+impl_fetch!(A, B, C, D, E, F, G, H, I, J);
+impl_fetch!(A, B, C, D, E, F, G, H, I, J, K);
+impl_fetch!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+/// Panics if any component in `access` is borrowed mutably and also borrowed
+/// a second time, which would alias a mutable reference. Accesses made by filters don't
+/// borrow anything, so they are ignored. `within` names what made the accesses, for
+/// the panic message.
+pub(crate) fn assert_disjoint(access: &[Access], within: &str) {
+    let borrowed: Vec<_> = access.iter().filter(|access| !access.filter).collect();
+    for (i, a) in borrowed.iter().enumerate() {
+        if borrowed[..i]
+            .iter()
+            .any(|b| a.id == b.id && (a.mutable || b.mutable))
+        {
+            panic!(
+                "`{}` is accessed mutably and more than once in {within}",
+                a.name
+            );
+        }
+    }
+}
+
+/// Reports that a [`QueryFilter`](crate::query::QueryFilter) checks for the component `T`.
+pub(crate) fn filter_access<T: Component>(access: &mut Vec<Access>) {
+    access.push(Access {
+        id: TypeId::of::<T>(),
+        name: type_name::<T>(),
+        mutable: false,
+        filter: true,
+    });
+}
+
+/// Returns whether `a` and `b` use the same component with at least one of them
+/// using it mutably, so they can't run at the same time.
+pub(crate) fn conflicts(a: &[Access], b: &[Access]) -> bool {
+    a.iter()
+        .any(|a| b.iter().any(|b| a.id == b.id && (a.mutable || b.mutable)))
+}
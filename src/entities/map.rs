@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+
+use super::EntityId;
+
+/// How far past the last slot an index given to [`EntityMap::insert_at`] may be for the
+/// slots up to it to be created. Values at indices further ahead are kept aside.
+const MAX_GAP: usize = 4096;
+
+/// A slot of an [`EntityMap`]. Its generation is odd while it holds a value, and even
+/// while it is free.
+struct Slot<V> {
+    generation: u32,
+    value: Option<V>,
+}
+impl<V> Slot<V> {
+    fn free() -> Self {
+        Self {
+            generation: 0,
+            value: None,
+        }
+    }
+}
+
+/// Why a value couldn't be inserted with [`EntityMap::insert_at`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum InsertAtError {
+    /// The index is 0, or the maximum index, which never refer to an entity.
+    Invalid,
+    /// The slot at the index holds another value.
+    Occupied,
+    /// The slot at the index has already been used by a later generation.
+    Stale,
+}
+
+/// The entities of a world, by ID. Like a `SlotMap`, an ID is made of the index of a
+/// slot and the generation of the slot when the value was inserted, so that IDs of
+/// removed values never refer to a later value. Values can also be inserted at a
+/// given ID, as long as it is newer than any that used its slot.
+pub(crate) struct EntityMap<V> {
+    // the slot of index `i` is at `i - 1`, as index 0 is never used
+    slots: Vec<Slot<V>>,
+    // the slots of indices more than `MAX_GAP` past the last slot when they were
+    // inserted at, so that a far-off ID doesn't create every slot before it; a slot is
+    // moved into `slots` once they reach its index
+    far: BTreeMap<u32, Slot<V>>,
+    // the indices of the free slots, the last one being reused first; slots that were
+    // filled with `insert_at` are skipped once they come up
+    free: Vec<u32>,
+    len: usize,
+}
+impl<V> Default for EntityMap<V> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            far: BTreeMap::new(),
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+}
+impl<V> EntityMap<V> {
+    /// Returns the number of values in the map.
+    #[cfg(feature = "serde")]
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    fn slot(&self, id: EntityId) -> Option<&Slot<V>> {
+        let index = id.index();
+        let slot = match self.slots.get((index as usize).checked_sub(1)?) {
+            Some(slot) => slot,
+            None => self.far.get(&index)?,
+        };
+        (slot.generation == id.generation()).then_some(slot)
+    }
+
+    fn slot_mut(&mut self, id: EntityId) -> Option<&mut Slot<V>> {
+        let index = id.index();
+        let slot = match self.slots.get_mut((index as usize).checked_sub(1)?) {
+            Some(slot) => slot,
+            None => self.far.get_mut(&index)?,
+        };
+        (slot.generation == id.generation()).then_some(slot)
+    }
+
+    /// Returns the value with ID `id`, if it is still in the map.
+    pub(crate) fn get(&self, id: EntityId) -> Option<&V> {
+        self.slot(id)?.value.as_ref()
+    }
+
+    /// Returns the value with ID `id` mutably, if it is still in the map.
+    pub(crate) fn get_mut(&mut self, id: EntityId) -> Option<&mut V> {
+        self.slot_mut(id)?.value.as_mut()
+    }
+
+    /// Creates the slots up to `index`, moving in the ones kept aside. The new slots
+    /// that are free are reused lowest index first.
+    fn grow_to(&mut self, index: u32) {
+        let first = self.slots.len() as u32 + 1;
+        let mut free = Vec::new();
+        for index in first..=index {
+            let slot = self.far.remove(&index).unwrap_or_else(Slot::free);
+            if slot.value.is_none() {
+                free.push(index);
+            }
+            self.slots.push(slot);
+        }
+        self.free.extend(free.into_iter().rev());
+    }
+
+    /// Inserts the value made by `f` from its new ID, returning the ID.
+    pub(crate) fn insert_with_key(&mut self, f: impl FnOnce(EntityId) -> V) -> EntityId {
+        loop {
+            while let Some(index) = self.free.pop() {
+                let slot = &mut self.slots[index as usize - 1];
+                if slot.value.is_some() {
+                    continue;
+                }
+                slot.generation = slot.generation.wrapping_add(1);
+                let id = EntityId::from_raw(index, slot.generation).unwrap();
+                slot.value = Some(f(id));
+                self.len += 1;
+                return id;
+            }
+            let index = u32::try_from(self.slots.len() + 1)
+                .ok()
+                .filter(|index| *index != u32::MAX)
+                .expect("too many entities");
+            self.grow_to(index);
+        }
+    }
+
+    /// Inserts the value made by `f` at `id`. The slots up to its index are created if
+    /// needed, and left free, unless the index is far past the last slot. Returns an
+    /// error if the slot is taken, or if `id` is older than an ID the slot already had.
+    pub(crate) fn insert_at(
+        &mut self,
+        id: EntityId,
+        f: impl FnOnce() -> V,
+    ) -> Result<(), InsertAtError> {
+        let index = id.index();
+        if index == 0 || index == u32::MAX {
+            return Err(InsertAtError::Invalid);
+        }
+        let slot = if index as usize > self.slots.len() + MAX_GAP {
+            self.far.entry(index).or_insert_with(Slot::free)
+        } else {
+            if index as usize > self.slots.len() {
+                self.grow_to(index);
+            }
+            &mut self.slots[index as usize - 1]
+        };
+        if slot.value.is_some() {
+            return Err(InsertAtError::Occupied);
+        }
+        if id.generation() < slot.generation {
+            return Err(InsertAtError::Stale);
+        }
+        slot.generation = id.generation();
+        slot.value = Some(f());
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes the value with ID `id`, returning it if it was still in the map.
+    pub(crate) fn remove(&mut self, id: EntityId) -> Option<V> {
+        let in_slots = (id.index() as usize) <= self.slots.len();
+        let slot = self.slot_mut(id)?;
+        let value = slot.value.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        // slots kept aside are only reused once they are moved in
+        if in_slots {
+            self.free.push(id.index());
+        }
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Iterates over the IDs and values in the map, in order of index.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (EntityId, &V)> {
+        let far = self.far.iter().map(|(index, slot)| (slot, *index));
+        self.slots
+            .iter()
+            .zip(1..)
+            .chain(far)
+            .filter_map(|(slot, index)| {
+                let value = slot.value.as_ref()?;
+                Some((EntityId::from_raw(index, slot.generation).unwrap(), value))
+            })
+    }
+
+    /// Iterates over the IDs and values in the map mutably, in order of index.
+    #[cfg(feature = "serde")]
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, &mut V)> {
+        let far = self.far.iter_mut().map(|(index, slot)| (slot, *index));
+        self.slots
+            .iter_mut()
+            .zip(1..)
+            .chain(far)
+            .filter_map(|(slot, index)| {
+                let value = slot.value.as_mut()?;
+                Some((EntityId::from_raw(index, slot.generation).unwrap(), value))
+            })
+    }
+
+    /// Iterates over the IDs in the map, in order of index.
+    pub(crate) fn keys(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.iter().map(|(id, _)| id)
+    }
+}
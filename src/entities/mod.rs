@@ -1,17 +1,34 @@
 use std::{
-    any::{Any, TypeId},
-    collections::{hash_map::Entry, HashMap},
+    any::{type_name, Any, TypeId},
+    collections::HashMap,
+    fmt, mem,
     ops::{Deref, DerefMut},
+    str::FromStr,
     sync::Arc,
 };
 
-use slotmap::new_key_type;
+use slotmap::{new_key_type, KeyData};
 use tokio::sync::{RwLockReadGuard, RwLockWriteGuard};
 
-use crate::world::World;
+use crate::{
+    archetype::{Archetype, Column, ComponentTicks},
+    commands::Command,
+    hierarchy,
+    world::World,
+};
+
+use self::component::{Component, ComponentInfo, StorageType};
 
 /// A builder for creating entities and adding them to a world.
 pub mod builder;
+/// Groups of components that can be added together.
+pub mod bundle;
+/// The trait implemented by components, and per-type component options.
+pub mod component;
+/// Fetching several components from an entity at once.
+pub mod fetch;
+/// The storage of a world's entities by ID.
+pub(crate) mod map;
 
 /// Error types for entity operations
 pub mod errors {
@@ -20,28 +37,176 @@ pub mod errors {
         fmt::{self, Display, Formatter},
     };
 
+    use super::EntityId;
+
     /// Error type returned from [`Entity::add`]
     /// A component of this type is already a part of the specified entity.
     #[derive(Debug)]
-    pub struct AlreadyExists;
+    pub struct AlreadyExists {
+        /// The type name of the component, as given by [`type_name`](std::any::type_name).
+        pub component: &'static str,
+        /// The entity the component was being added to, or `None` if it was being added to
+        /// an [`EntityBuilder`](super::builder::EntityBuilder).
+        pub entity: Option<EntityId>,
+    }
     impl Display for AlreadyExists {
         fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-            write!(f, "component already exists")
+            match self.entity {
+                Some(entity) => write!(
+                    f,
+                    "component `{}` already exists on entity {entity}",
+                    self.component
+                ),
+                None => write!(f, "component `{}` already exists", self.component),
+            }
         }
     }
     impl Error for AlreadyExists {}
+
+    /// Error type returned from [`EntityBuilder::build_many`](super::builder::EntityBuilder::build_many)
+    /// A component was added by value, so it can't be given to more than one entity.
+    #[derive(Debug)]
+    pub struct NotRepeatable;
+    impl Display for NotRepeatable {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "component cannot be repeated")
+        }
+    }
+    impl Error for NotRepeatable {}
+
+    /// Error type returned from [`World::compare_and_update`](crate::world::World::compare_and_update)
+    /// The component was modified or removed since the expected version was read.
+    /// The rejected value is handed back so the update can be retried.
+    pub struct VersionMismatch<T> {
+        /// The value that was not written.
+        pub value: T,
+        /// The component's current version, or `None` if the entity or component no
+        /// longer exists.
+        pub current: Option<u64>,
+    }
+    impl<T> Display for VersionMismatch<T> {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            match self.current {
+                Some(current) => write!(f, "component version changed to {current}"),
+                None => write!(f, "component no longer exists"),
+            }
+        }
+    }
+    // the value is left out so that `T` doesn't need to implement `Debug`
+    impl<T> fmt::Debug for VersionMismatch<T> {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            f.debug_struct("VersionMismatch")
+                .field("current", &self.current)
+                .finish_non_exhaustive()
+        }
+    }
+    impl<T> Error for VersionMismatch<T> {}
+
+    /// Error type returned from parsing an [`EntityId`](super::EntityId)
+    /// The string was not of the form `index:generation`.
+    #[derive(Debug)]
+    pub struct ParseEntityIdError;
+    impl Display for ParseEntityIdError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "invalid entity id, expected `index:generation`")
+        }
+    }
+    impl Error for ParseEntityIdError {}
 }
 
 new_key_type! {
     /// Unique identifier for an entity
+    ///
+    /// An ID has a stable textual form, `index:generation`, which is what its
+    /// [`Display`](fmt::Display) and [`FromStr`] implementations (and, with the `serde`
+    /// feature, its serde implementations) use:
+    /// ```rust
+    /// use jest::{world::World, entities::{EntityId, builder::EntityBuilder}};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///     let entity_id = EntityBuilder::new().build(&world).await;
+    ///
+    ///     let text = entity_id.to_string();
+    ///     assert_eq!(text.parse::<EntityId>().unwrap(), entity_id);
+    ///
+    ///     let (index, generation) = (entity_id.index(), entity_id.generation());
+    ///     assert_eq!(EntityId::from_raw(index, generation), Some(entity_id));
+    /// }
+    /// ```
     pub struct EntityId;
 }
+impl EntityId {
+    /// Creates an ID from its raw index and generation, as returned by
+    /// [`index`](EntityId::index) and [`generation`](EntityId::generation).
+    /// Returns `None` if `generation` is even, as such an ID can never refer to an entity.
+    ///
+    /// The resulting ID is only meaningful in the world it came from. Because of the
+    /// generation, an ID whose entity has been removed will not refer to a newer entity
+    /// that reused its index.
+    pub fn from_raw(index: u32, generation: u32) -> Option<Self> {
+        if generation & 1 == 0 {
+            return None;
+        }
+        Some(KeyData::from_ffi(u64::from(generation) << 32 | u64::from(index)).into())
+    }
+
+    /// Returns the index of the slot this entity occupies in its world. Indices are
+    /// reused after an entity is removed.
+    pub fn index(self) -> u32 {
+        self.0.as_ffi() as u32
+    }
+
+    /// Returns the generation of this ID, which distinguishes it from other entities
+    /// that have occupied the same index. Live generations are always odd.
+    pub fn generation(self) -> u32 {
+        (self.0.as_ffi() >> 32) as u32
+    }
+}
+impl fmt::Display for EntityId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.index(), self.generation())
+    }
+}
+impl FromStr for EntityId {
+    type Err = errors::ParseEntityIdError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, generation) = s.split_once(':').ok_or(errors::ParseEntityIdError)?;
+        let index: u32 = index.parse().map_err(|_| errors::ParseEntityIdError)?;
+        let generation: u32 = generation.parse().map_err(|_| errors::ParseEntityIdError)?;
+        EntityId::from_raw(index, generation).ok_or(errors::ParseEntityIdError)
+    }
+}
+#[cfg(feature = "serde")]
+impl serde::Serialize for EntityId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for EntityId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = EntityId;
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "an entity id of the form `index:generation`")
+            }
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<EntityId, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+        deserializer.deserialize_str(Visitor)
+    }
+}
 
 /// Entities are the base of ECS. An entity represents a single object in the world.
 /// It is comprised of many components, which are just simple bits of data.
 /// A component can be anything, so long as it satisfies
 /// [`'static`](https://doc.rust-lang.org/rust-by-example/scope/lifetime/static_lifetime.html#trait-bound)
-/// (tl;dr: it has no references) and [`Send`].
+/// (tl;dr: it has no references), [`Send`] and [`Sync`], since it may be read from
+/// several systems running in parallel.
 ///
 /// # Usage
 /// ## Constructing an entity
@@ -99,47 +264,813 @@ new_key_type! {
 /// }
 /// ```
 pub struct Entity {
-    components: HashMap<TypeId, Box<dyn Any + Send>>,
+    storage: Storage,
+    last_version: u64,
+    // set by the world on insertion
+    pub(crate) id: EntityId,
     // reference counter to the world
-    _world: Arc<World>,
+    pub(crate) world: Arc<World>,
 }
+
+/// Where the components of an [`Entity`] are stored.
+enum Storage {
+    /// Not in a world: every component is boxed, along with its ticks.
+    Detached(HashMap<TypeId, (Box<dyn Component>, ComponentTicks)>),
+    /// In a world: the table components are in a row of the archetype matching their
+    /// types, and every sparse component is in a row of the sparse set of its type.
+    Attached {
+        archetype: Arc<Archetype>,
+        row: usize,
+        // sorted by type ID
+        sparse: Vec<(TypeId, Arc<Archetype>, usize)>,
+    },
+}
+
 impl Entity {
+    pub(crate) fn new(components: HashMap<TypeId, Box<dyn Component>>, world: Arc<World>) -> Self {
+        Self {
+            storage: Storage::Detached(
+                components
+                    .into_iter()
+                    .map(|(id, component)| (id, (component, ComponentTicks::default())))
+                    .collect(),
+            ),
+            last_version: 0,
+            id: EntityId::default(),
+            world,
+        }
+    }
+
+    /// Moves the components into the world's storage, once the entity has been given an ID.
+    pub(crate) fn attach(&mut self) {
+        let Storage::Detached(components) = &mut self.storage else {
+            return;
+        };
+        // the components count as added to the world now
+        let tick = self.world.change_tick();
+        let (mut sparse, mut table): (Vec<_>, Vec<_>) = components
+            .drain()
+            .map(|(id, (component, ticks))| {
+                let ticks = ComponentTicks {
+                    added: tick,
+                    changed: tick,
+                    ..ticks
+                };
+                (id, (component, ticks))
+            })
+            .partition(|(id, _)| self.world.storage(*id) == StorageType::SparseSet);
+        table.sort_unstable_by_key(|(id, _)| *id);
+        sparse.sort_unstable_by_key(|(id, _)| *id);
+        let types: Vec<_> = table.iter().map(|(id, _)| *id).collect();
+        let archetype = self.world.archetype(&types, || {
+            table
+                .iter()
+                .map(|(_, (component, _))| (**component).new_column())
+                .collect()
+        });
+        let row = archetype.alloc(self.id);
+        for ((_, (component, ticks)), column) in table.into_iter().zip(archetype.columns()) {
+            // SAFETY: the row was just allocated, and the types match
+            unsafe { column.write(row, component, ticks) };
+        }
+        let sparse = sparse
+            .into_iter()
+            .map(|(id, (component, ticks))| self.write_sparse(id, component, ticks))
+            .collect();
+        self.storage = Storage::Attached {
+            archetype,
+            row,
+            sparse,
+        };
+    }
+
+    /// Moves the components out of the world's storage, once the entity has been removed
+    /// from it.
+    pub(crate) fn detach(&mut self) {
+        let Storage::Attached { archetype, row, .. } = &self.storage else {
+            return;
+        };
+        let mut components: HashMap<_, _> = archetype
+            .types()
+            .iter()
+            .zip(archetype.columns())
+            // SAFETY: the row belongs to this entity, which is borrowed mutably
+            .map(|(id, column)| (*id, unsafe { column.take(*row) }))
+            .collect();
+        archetype.free(*row);
+        components.extend(
+            self.take_sparse(|_| false)
+                .into_iter()
+                .map(|(id, component, ticks)| (id, (component, ticks))),
+        );
+        for id in components.keys() {
+            self.world.record_removal(*id, self.id);
+        }
+        self.storage = Storage::Detached(components);
+    }
+
+    /// Moves `component` into a new row of the sparse set of its type, returning where it
+    /// was stored.
+    fn write_sparse(
+        &self,
+        id: TypeId,
+        component: Box<dyn Component>,
+        ticks: ComponentTicks,
+    ) -> (TypeId, Arc<Archetype>, usize) {
+        let set = self.world.sparse_set(id, || (*component).new_column());
+        let row = set.alloc(self.id);
+        // SAFETY: the row was just allocated
+        unsafe { set.columns()[0].write(row, component, ticks) };
+        (id, set, row)
+    }
+
+    /// Moves every sparse component whose type isn't `kept` out of its sparse set, along
+    /// with its ticks.
+    fn take_sparse(
+        &mut self,
+        kept: impl Fn(TypeId) -> bool,
+    ) -> Vec<(TypeId, Box<dyn Component>, ComponentTicks)> {
+        let Storage::Attached { sparse, .. } = &mut self.storage else {
+            return Vec::new();
+        };
+        let (kept, taken) = mem::take(sparse)
+            .into_iter()
+            .partition(|(id, ..)| kept(*id));
+        *sparse = kept;
+        taken
+            .into_iter()
+            .map(|(id, set, row)| {
+                // SAFETY: the row belongs to this entity, which is borrowed mutably
+                let (component, ticks) = unsafe { set.columns()[0].take(row) };
+                set.free(row);
+                (id, component, ticks)
+            })
+            .collect()
+    }
+
+    /// Moves the table components of an attached entity into the archetype storing exactly
+    /// `types`, which must be sorted. Columns for types the entity didn't have yet are
+    /// created by `new_column`. Returns the components of the types it no longer has,
+    /// along with their ticks.
+    fn relocate(
+        &mut self,
+        types: &[TypeId],
+        mut new_column: impl FnMut(TypeId) -> Box<dyn Column>,
+    ) -> Vec<(TypeId, Box<dyn Component>, ComponentTicks)> {
+        let Storage::Attached { archetype, row, .. } = &self.storage else {
+            unreachable!("only attached entities are relocated");
+        };
+        let (archetype, row) = (archetype.clone(), *row);
+        let target = self.world.archetype(types, || {
+            types
+                .iter()
+                .map(|id| match archetype.column(*id) {
+                    Some(column) => column.empty(),
+                    None => new_column(*id),
+                })
+                .collect()
+        });
+        let new_row = target.alloc(self.id);
+        let mut removed = Vec::new();
+        for (id, column) in archetype.types().iter().zip(archetype.columns()) {
+            // SAFETY: the old row belongs to this entity, which is borrowed mutably, and the
+            // new row was just allocated
+            match target.column(*id) {
+                Some(to) => unsafe { column.move_to(row, to, new_row) },
+                None => {
+                    let (component, ticks) = unsafe { column.take(row) };
+                    removed.push((*id, component, ticks));
+                }
+            }
+        }
+        archetype.free(row);
+        if let Storage::Attached { archetype, row, .. } = &mut self.storage {
+            *archetype = target;
+            *row = new_row;
+        }
+        removed
+    }
+
+    /// Returns the column and row of an attached entity's component with type ID `id`.
+    fn column(&self, id: TypeId) -> Option<(&dyn Column, usize)> {
+        let Storage::Attached {
+            archetype,
+            row,
+            sparse,
+        } = &self.storage
+        else {
+            return None;
+        };
+        if let Some(column) = archetype.column(id) {
+            return Some((column, *row));
+        }
+        let index = sparse.binary_search_by_key(&id, |(id, ..)| *id).ok()?;
+        let (_, set, row) = &sparse[index];
+        Some((&*set.columns()[0], *row))
+    }
+
+    /// Returns the type IDs of every component of this entity.
+    fn types(&self) -> Vec<TypeId> {
+        match &self.storage {
+            Storage::Detached(components) => components.keys().copied().collect(),
+            Storage::Attached {
+                archetype, sparse, ..
+            } => archetype
+                .types()
+                .iter()
+                .copied()
+                .chain(sparse.iter().map(|(id, ..)| *id))
+                .collect(),
+        }
+    }
+
+    /// Returns whether this entity has a component with type ID `id`.
+    fn contains(&self, id: TypeId) -> bool {
+        match &self.storage {
+            Storage::Detached(components) => components.contains_key(&id),
+            Storage::Attached { .. } => self.column(id).is_some(),
+        }
+    }
+
+    /// Returns every component of this entity along with its type ID.
+    pub(crate) fn components(&self) -> Vec<(TypeId, &dyn Component)> {
+        match &self.storage {
+            Storage::Detached(components) => components
+                .iter()
+                .map(|(id, (component, _))| (*id, &**component))
+                .collect(),
+            Storage::Attached { .. } => self
+                .types()
+                .into_iter()
+                .map(|id| {
+                    let (column, row) = self.column(id).unwrap();
+                    // SAFETY: the row belongs to this entity, which is borrowed
+                    (id, unsafe { column.get_dyn(row) })
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns a pointer to the component of type `T` in `entity`, if it exists. The
+    /// entity is only borrowed immutably, so it may be behind a read lock.
+    ///
+    /// # Safety
+    /// `entity` must be valid, and the returned pointer is only valid until the entity
+    /// is next modified.
+    pub(crate) unsafe fn component_ptr<T: Component>(entity: *const Entity) -> Option<*const T> {
+        match &(*entity).storage {
+            Storage::Detached(components) => {
+                let (component, _) = components.get(&TypeId::of::<T>())?;
+                let component: &dyn Any = &**component;
+                Some(component.downcast_ref::<T>().unwrap())
+            }
+            Storage::Attached { .. } => {
+                let (column, row) = (*entity).column(TypeId::of::<T>())?;
+                Some(column.typed::<T>().get(row))
+            }
+        }
+    }
+
+    /// Returns a mutable pointer to the component of type `T` in `entity`, if it exists.
+    /// Attached components live in their columns, so only a detached entity is
+    /// borrowed mutably.
+    ///
+    /// # Safety
+    /// `entity` must be valid and not borrowed elsewhere, and the returned pointer is
+    /// only valid until the entity is next modified.
+    pub(crate) unsafe fn component_mut_ptr<T: Component>(entity: *mut Entity) -> Option<*mut T> {
+        match &mut (*entity).storage {
+            Storage::Detached(components) => {
+                let (component, _) = components.get_mut(&TypeId::of::<T>())?;
+                let component: &mut dyn Any = &mut **component;
+                Some(component.downcast_mut::<T>().unwrap())
+            }
+            Storage::Attached { .. } => {
+                let (column, row) = (*entity).column(TypeId::of::<T>())?;
+                Some(column.typed::<T>().get(row))
+            }
+        }
+    }
+
+    /// Returns a pointer to the ticks of the component with type ID `id`, if it exists.
+    fn ticks_ptr(&mut self, id: TypeId) -> Option<*mut ComponentTicks> {
+        match &mut self.storage {
+            Storage::Detached(components) => components
+                .get_mut(&id)
+                .map(|(_, ticks)| ticks as *mut ComponentTicks),
+            Storage::Attached { .. } => {
+                let (column, row) = self.column(id)?;
+                Some(column.ticks_ptr(row))
+            }
+        }
+    }
+
+    /// Returns the ticks of the component with type ID `id`, if it exists.
+    pub(crate) fn ticks(&self, id: TypeId) -> Option<ComponentTicks> {
+        match &self.storage {
+            Storage::Detached(components) => components.get(&id).map(|(_, ticks)| *ticks),
+            Storage::Attached { .. } => {
+                let (column, row) = self.column(id)?;
+                // SAFETY: the row belongs to this entity, which is borrowed
+                Some(unsafe { *column.ticks_ptr(row) })
+            }
+        }
+    }
+
+    /// Returns a version that hasn't been used by this entity yet.
+    fn next_version(&mut self) -> u64 {
+        self.last_version += 1;
+        self.last_version
+    }
+
+    /// Gives the component with type ID `id` a new version and marks it as changed
+    /// at `tick`, returning the version. If the type is [indexed](crate::indexes), the
+    /// entity is indexed again before the next lookup.
+    pub(crate) fn touch(&mut self, id: TypeId, tick: u64) -> u64 {
+        let version = self.next_version();
+        if let Some(ptr) = self.ticks_ptr(id) {
+            // SAFETY: the entity is borrowed mutably
+            unsafe {
+                (*ptr).version = version;
+                (*ptr).changed = tick;
+            }
+            if matches!(self.storage, Storage::Attached { .. }) {
+                if let Some(index) = self.world.indexes.read().unwrap().get(&id) {
+                    index.mark(self.id);
+                }
+            }
+        }
+        version
+    }
+
+    /// Adds components of types the entity doesn't have yet, giving each a new version
+    /// and marking it as added during the current world tick.
+    fn insert(&mut self, components: Vec<(TypeId, Box<dyn Component>)>) {
+        let tick = self.world.change_tick();
+        let components: Vec<_> = components
+            .into_iter()
+            .map(|(id, component)| {
+                let ticks = ComponentTicks {
+                    version: self.next_version(),
+                    added: tick,
+                    changed: tick,
+                };
+                (id, component, ticks)
+            })
+            .collect();
+        if let Storage::Detached(map) = &mut self.storage {
+            for (id, component, ticks) in components {
+                map.insert(id, (component, ticks));
+            }
+            return;
+        }
+
+        let (sparse, mut table): (Vec<_>, Vec<_>) = components
+            .into_iter()
+            .partition(|(id, ..)| self.world.storage(*id) == StorageType::SparseSet);
+        for (id, component, ticks) in sparse {
+            let entry = self.write_sparse(id, component, ticks);
+            let Storage::Attached { sparse, .. } = &mut self.storage else {
+                unreachable!();
+            };
+            let index = sparse.partition_point(|(other, ..)| *other < id);
+            sparse.insert(index, entry);
+        }
+        if table.is_empty() {
+            return;
+        }
+
+        let Storage::Attached { archetype, .. } = &self.storage else {
+            unreachable!();
+        };
+        let mut types = archetype.types().to_vec();
+        types.extend(table.iter().map(|(id, ..)| *id));
+        types.sort_unstable();
+        self.relocate(&types, |id| {
+            let (_, component, _) = table.iter().find(|(other, ..)| *other == id).unwrap();
+            (**component).new_column()
+        });
+        let Storage::Attached { archetype, row, .. } = &self.storage else {
+            unreachable!();
+        };
+        for (id, component, ticks) in table.drain(..) {
+            // SAFETY: the row was just allocated, without a component of this type
+            unsafe { archetype.column(id).unwrap().write(*row, component, ticks) };
+        }
+    }
+
+    /// Removes the component with type ID `id`, which must exist.
+    fn take(&mut self, id: TypeId) -> Box<dyn Component> {
+        match &mut self.storage {
+            Storage::Detached(components) => components.remove(&id).unwrap().0,
+            Storage::Attached {
+                archetype, sparse, ..
+            } => {
+                self.world.record_removal(id, self.id);
+                if let Ok(index) = sparse.binary_search_by_key(&id, |(id, ..)| *id) {
+                    let (_, set, row) = sparse.remove(index);
+                    // SAFETY: the row belongs to this entity, which is borrowed mutably
+                    let (component, _) = unsafe { set.columns()[0].take(row) };
+                    set.free(row);
+                    return component;
+                }
+                let mut types = archetype.types().to_vec();
+                types.retain(|other| *other != id);
+                let mut removed = self.relocate(&types, |_| unreachable!());
+                removed.pop().unwrap().1
+            }
+        }
+    }
+
+    /// Returns the options registered for the component type `id`, if any.
+    fn info(&self, id: TypeId) -> Option<ComponentInfo> {
+        self.world.components.read().unwrap().get(&id).copied()
+    }
+
+    /// Indexes the component with type ID `id` if it is a [relation](crate::relations)
+    /// or [indexed](crate::indexes), then runs the `on_add` hook of its type.
+    fn on_add(&mut self, id: TypeId) {
+        self.link(id);
+        if let Some(info) = self.info(id) {
+            (info.on_add)(self);
+        }
+    }
+
+    /// Removes the component with type ID `id` from the relation and value indexes,
+    /// then runs the `on_remove` hook of its type.
+    fn on_remove(&mut self, id: TypeId) {
+        self.unlink(id);
+        if let Some(info) = self.info(id) {
+            (info.on_remove)(self);
+        }
+    }
+
+    /// Indexes the component with type ID `id` if it is a relation or indexed, and the
+    /// entity is in the world.
+    fn link(&self, id: TypeId) {
+        let Some((column, row)) = self.column(id) else {
+            return;
+        };
+        if let Some(index) = self.world.relations.write().unwrap().get_mut(&id) {
+            // SAFETY: the row belongs to this entity, which is borrowed
+            index.link(self.id, unsafe { column.get_dyn(row) });
+        }
+        self.reindex(id);
+    }
+
+    /// Removes the component with type ID `id` from the relation and value indexes, if
+    /// it is in one.
+    fn unlink(&self, id: TypeId) {
+        if let Some(index) = self.world.relations.write().unwrap().get_mut(&id) {
+            index.unlink(self.id);
+        }
+        if let Some(index) = self.world.indexes.write().unwrap().get_mut(&id) {
+            index.unlink(self.id);
+        }
+    }
+
+    /// Indexes the component with type ID `id` under its current key, if it is indexed
+    /// and the entity is in the world.
+    pub(crate) fn reindex(&self, id: TypeId) {
+        let mut indexes = self.world.indexes.write().unwrap();
+        let Some(index) = indexes.get_mut(&id) else {
+            return;
+        };
+        match self.column(id) {
+            // SAFETY: the row belongs to this entity, which is borrowed
+            Some((column, row)) => index.link(self.id, unsafe { column.get_dyn(row) }),
+            None => index.unlink(self.id),
+        }
+    }
+
+    /// Runs the `on_add` hook of every component, once the entity has been spawned.
+    pub(crate) fn spawned(&mut self) {
+        for id in self.types() {
+            self.on_add(id);
+        }
+    }
+
+    /// Runs the `on_remove` hook of every component, once the entity has been despawned.
+    pub(crate) fn despawned(&mut self) {
+        for id in self.types() {
+            self.on_remove(id);
+        }
+    }
+
+    /// Returns the ID of this entity within its world.
+    pub fn id(&self) -> EntityId {
+        self.id
+    }
+
     /// Adds a component of type `T` to the entity, returning [`AlreadyExists`](errors::AlreadyExists) if
-    /// a component of the same type already exists.. `T` must satisfy
-    /// [`'static`](https://doc.rust-lang.org/rust-by-example/scope/lifetime/static_lifetime.html#trait-bound)
-    /// and [`Send`].
-    pub fn add<T: Any + Send>(&mut self, component: T) -> Result<(), errors::AlreadyExists> {
-        match self.components.entry(TypeId::of::<T>()) {
-            Entry::Occupied(_) => Err(errors::AlreadyExists),
-            Entry::Vacant(entry) => {
-                entry.insert(Box::new(component));
-                // TODO: notify world
-                Ok(())
+    /// a component of the same type already exists.. Any type satisfying
+    /// [`'static`](https://doc.rust-lang.org/rust-by-example/scope/lifetime/static_lifetime.html#trait-bound),
+    /// [`Send`] and [`Sync`] is a [`Component`](trait@Component).
+    ///
+    /// # Panics
+    /// Panics if `T` is [`Parent`](hierarchy::Parent) or [`Children`](hierarchy::Children),
+    /// which are added by [`EntityMut::add_child`].
+    pub fn add<T: Component>(&mut self, component: T) -> Result<(), errors::AlreadyExists> {
+        hierarchy::assert_not_link(TypeId::of::<T>(), type_name::<T>());
+        if self.contains(TypeId::of::<T>()) {
+            return Err(errors::AlreadyExists {
+                component: type_name::<T>(),
+                entity: Some(self.id),
+            });
+        }
+        self.insert(vec![(TypeId::of::<T>(), Box::new(component))]);
+        // TODO: notify world
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            entity = ?self.id,
+            component = type_name::<T>(),
+            "component added"
+        );
+        self.on_add(TypeId::of::<T>());
+        Ok(())
+    }
+
+    /// Adds every component of `bundle` to the entity, returning [`AlreadyExists`](errors::AlreadyExists)
+    /// if any of them is already present or the bundle contains the same type twice.
+    /// Nothing is added unless the whole bundle can be.
+    ///
+    /// # Panics
+    /// Panics if the bundle contains a [`Parent`](hierarchy::Parent) or
+    /// [`Children`](hierarchy::Children), like [`add`](Entity::add).
+    /// ```rust
+    /// use jest::{world::World, entities::{builder::EntityBuilder, bundle::Bundle}};
+    ///
+    /// struct Position(f32);
+    /// struct Velocity(f32);
+    ///
+    /// #[derive(Bundle)]
+    /// struct Movement {
+    ///     position: Position,
+    ///     velocity: Velocity,
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///     let entity_id = EntityBuilder::new().build(&world).await;
+    ///
+    ///     let mut entity = world.get_mut(entity_id).await.unwrap();
+    ///     let movement = Movement { position: Position(0.0), velocity: Velocity(1.0) };
+    ///     entity.add_bundle(movement).unwrap();
+    ///     assert_eq!(entity.get::<Velocity>().unwrap().0, 1.0);
+    ///     assert!(entity.add_bundle((Position(1.0),)).is_err());
+    /// }
+    /// ```
+    pub fn add_bundle<B: bundle::Bundle>(
+        &mut self,
+        bundle: B,
+    ) -> Result<(), errors::AlreadyExists> {
+        let types = B::component_types();
+        for (i, (id, name)) in types.iter().enumerate() {
+            hierarchy::assert_not_link(*id, name);
+            if self.contains(*id) || types[..i].iter().any(|(other, _)| other == id) {
+                return Err(errors::AlreadyExists {
+                    component: name,
+                    entity: Some(self.id),
+                });
             }
         }
+        let mut components = Vec::with_capacity(types.len());
+        bundle.take_components(&mut |id, component| components.push((id, component)));
+        self.insert(components);
+        #[cfg(feature = "tracing")]
+        for (_, name) in &types {
+            tracing::debug!(entity = ?self.id, component = name, "component added");
+        }
+        for (id, _) in types {
+            self.on_add(id);
+        }
+        Ok(())
+    }
+
+    /// Inserts `component` into the entity, returning the component of type `T` it replaced,
+    /// if there was one.
+    ///
+    /// # Panics
+    /// Panics if `T` is [`Parent`](hierarchy::Parent) or [`Children`](hierarchy::Children),
+    /// like [`add`](Entity::add).
+    /// ```rust
+    /// use jest::{world::World, entities::builder::EntityBuilder};
+    ///
+    /// struct Health(u32);
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///     let entity_id = EntityBuilder::new().build(&world).await;
+    ///     let mut entity = world.get_mut(entity_id).await.unwrap();
+    ///
+    ///     assert!(entity.replace(Health(100)).is_none());
+    ///     let old = entity.replace(Health(75)).unwrap();
+    ///     assert_eq!(old.0 - entity.get::<Health>().unwrap().0, 25);
+    /// }
+    /// ```
+    pub fn replace<T: Component>(&mut self, component: T) -> Option<T> {
+        hierarchy::assert_not_link(TypeId::of::<T>(), type_name::<T>());
+        self.replace_linked(component)
+    }
+
+    /// Like [`replace`](Entity::replace), but also for the [`Parent`](hierarchy::Parent)
+    /// and [`Children`](hierarchy::Children). The caller takes care of the other side of
+    /// the link.
+    pub(crate) fn replace_linked<T: Component>(&mut self, component: T) -> Option<T> {
+        if let Some(old) = self.get_mut::<T>() {
+            let old = mem::replace(old, component);
+            self.link(TypeId::of::<T>());
+            return Some(old);
+        }
+        self.insert(vec![(TypeId::of::<T>(), Box::new(component))]);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            entity = ?self.id,
+            component = type_name::<T>(),
+            "component added"
+        );
+        self.on_add(TypeId::of::<T>());
+        None
     }
 
     /// Removes a component of type `T` from the entity, returning it if it exists.
-    pub fn remove<T: Any + Send>(&mut self) -> Option<T> {
-        self.components
-            .remove(&TypeId::of::<T>())
-            .map(|c| *c.downcast::<T>().unwrap())
+    ///
+    /// [`Parent`](hierarchy::Parent) and [`Children`](hierarchy::Children) are never
+    /// removed this way, as that would leave the other side of the link behind; use
+    /// [`EntityMut::remove_child`] instead, or despawn the entity.
+    pub fn remove<T: Component>(&mut self) -> Option<T> {
+        let component: Box<dyn Any> = self.remove_dyn(TypeId::of::<T>())?;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            entity = ?self.id,
+            component = type_name::<T>(),
+            "component removed"
+        );
+        Some(*component.downcast::<T>().unwrap())
+    }
+
+    /// Removes the component with type ID `id`, returning it if it exists and isn't
+    /// part of the hierarchy.
+    pub(crate) fn remove_dyn(&mut self, id: TypeId) -> Option<Box<dyn Component>> {
+        if hierarchy::is_link(id) {
+            return None;
+        }
+        self.remove_linked(id)
+    }
+
+    /// Removes the component with type ID `id`, returning it if it exists, even if it is
+    /// part of the hierarchy. The caller takes care of the other side of the link.
+    pub(crate) fn remove_linked(&mut self, id: TypeId) -> Option<Box<dyn Component>> {
+        if !self.contains(id) {
+            return None;
+        }
+        self.on_remove(id);
+        if !self.contains(id) {
+            return None;
+        }
+        Some(self.take(id))
+    }
+
+    /// Removes every component from the entity, yielding each one along with its type ID.
+    /// If the entity is in a world, the `on_remove` hooks of its components run first, and
+    /// like with [`remove`](Entity::remove), its [`Parent`](hierarchy::Parent) and
+    /// [`Children`](hierarchy::Children) are left in place.
+    /// To move a removed entity into another world, convert it into an
+    /// [`EntityBuilder`](builder::EntityBuilder) instead, which drains it for you:
+    /// ```rust
+    /// use jest::{world::World, entities::builder::EntityBuilder};
+    ///
+    /// struct Health(u32);
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///     let other = World::new();
+    ///
+    ///     let mut builder = EntityBuilder::new();
+    ///     builder.add(Health(100)).unwrap();
+    ///     let entity_id = builder.build(&world).await;
+    ///
+    ///     let entity = world.remove(entity_id).await.unwrap();
+    ///     let moved = EntityBuilder::from(entity).build(&other).await;
+    ///     assert_eq!(other.get(moved).await.unwrap().get::<Health>().unwrap().0, 100);
+    /// }
+    /// ```
+    pub fn drain(&mut self) -> impl Iterator<Item = (TypeId, Box<dyn Component>)> + '_ {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(entity = ?self.id, "components drained");
+        if let Storage::Attached { .. } = self.storage {
+            for id in self.types() {
+                if !hierarchy::is_link(id) {
+                    self.on_remove(id);
+                }
+            }
+        }
+        let components: Vec<_> = match &mut self.storage {
+            Storage::Detached(components) => components
+                .drain()
+                .map(|(id, (component, _))| (id, component))
+                .collect(),
+            Storage::Attached { archetype, .. } => {
+                let kept: Vec<_> = archetype
+                    .types()
+                    .iter()
+                    .copied()
+                    .filter(|id| hierarchy::is_link(*id))
+                    .collect();
+                let mut components = self.relocate(&kept, |_| unreachable!());
+                components.extend(self.take_sparse(hierarchy::is_link));
+                for (id, ..) in &components {
+                    self.unlink(*id);
+                    self.world.record_removal(*id, self.id);
+                }
+                components
+                    .into_iter()
+                    .map(|(id, component, _)| (id, component))
+                    .collect()
+            }
+        };
+        components.into_iter()
     }
 
     /// Get an immutable reference to the component of type `T` in this entity,
     /// if it exists.
-    pub fn get<T: Any + Send>(&self) -> Option<&T> {
-        self.components
-            .get(&TypeId::of::<T>())
-            .map(|c| c.downcast_ref::<T>().unwrap())
+    pub fn get<T: Component>(&self) -> Option<&T> {
+        match &self.storage {
+            Storage::Detached(components) => {
+                let (component, _) = components.get(&TypeId::of::<T>())?;
+                let component: &dyn Any = &**component;
+                component.downcast_ref::<T>()
+            }
+            Storage::Attached { .. } => {
+                let (column, row) = self.column(TypeId::of::<T>())?;
+                // SAFETY: the row belongs to this entity, which is borrowed
+                Some(unsafe { &*column.typed::<T>().get(row) })
+            }
+        }
     }
 
     /// Get a mutable reference to the component of type `T` in this entity,
     /// if it exists.
-    pub fn get_mut<T: Any + Send>(&mut self) -> Option<&mut T> {
-        self.components
-            .get_mut(&TypeId::of::<T>())
-            .map(|c| c.downcast_mut::<T>().unwrap())
+    pub fn get_mut<T: Component>(&mut self) -> Option<&mut T> {
+        // SAFETY: `self` is borrowed mutably for as long as the reference lives
+        let component = unsafe { Self::component_mut_ptr::<T>(self)? };
+        self.touch(TypeId::of::<T>(), self.world.change_tick());
+        Some(unsafe { &mut *component })
+    }
+
+    /// Returns the current version of the component of type `T` in this entity, if it exists.
+    ///
+    /// The version changes whenever the component may have been modified: when it is
+    /// added or replaced, and whenever a mutable reference to it is handed out. It never
+    /// repeats within an entity, so it can be used to detect concurrent changes; see
+    /// [`World::compare_and_update`].
+    pub fn version<T: Component>(&self) -> Option<u64> {
+        self.ticks(TypeId::of::<T>()).map(|ticks| ticks.version)
+    }
+
+    /// Get several components of this entity at once, if they all exist. `F` is a
+    /// tuple of `&T` and `&mut T` references, or a single reference.
+    ///
+    /// # Panics
+    /// Panics if the same component type is requested mutably more than once, or
+    /// both mutably and immutably.
+    /// ```rust
+    /// use jest::{world::World, entities::builder::EntityBuilder};
+    ///
+    /// struct Attack(u32);
+    /// struct Defense(u32);
+    /// struct Health(u32);
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///
+    ///     let mut builder = EntityBuilder::new();
+    ///     builder
+    ///         .add_bundle((Attack(30), Defense(10), Health(100)))
+    ///         .unwrap();
+    ///     let entity_id = builder.build(&world).await;
+    ///     let mut entity = world.get_mut(entity_id).await.unwrap();
+    ///
+    ///     let (attack, defense, health) = entity
+    ///         .get_many::<(&Attack, &Defense, &mut Health)>()
+    ///         .unwrap();
+    ///     health.0 -= attack.0 - defense.0;
+    ///     assert_eq!(entity.get::<Health>().unwrap().0, 80);
+    /// }
+    /// ```
+    pub fn get_many<F: fetch::Fetch>(&mut self) -> Option<F::Item<'_>> {
+        let mut access = Vec::new();
+        F::access(&mut access);
+        fetch::assert_disjoint(&access, "the same fetch");
+        let last_run = self.world.last_change_tick();
+        let this_run = self.world.change_tick();
+        // SAFETY: the accesses were checked to be disjoint, and `self` is borrowed
+        // mutably for as long as the references live
+        unsafe { F::fetch(self, last_run, this_run) }
     }
 }
 
@@ -184,3 +1115,31 @@ impl DerefMut for EntityMut<'_> {
         &mut self.inner
     }
 }
+impl EntityMut<'_> {
+    /// Queues the removal of this entity from the world, releasing this reference. Like
+    /// [`Commands::despawn`](crate::commands::Commands::despawn), the entity is removed
+    /// when the world's commands are applied, as it can't be while it is borrowed.
+    /// ```rust
+    /// use jest::{world::World, entities::builder::EntityBuilder};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///     let id = EntityBuilder::new().build(&world).await;
+    ///     world.get_mut(id).await.unwrap().despawn();
+    ///     assert!(world.get(id).await.is_some());
+    ///
+    ///     world.apply_commands().await;
+    ///     assert!(world.get(id).await.is_none());
+    /// }
+    /// ```
+    pub fn despawn(self) {
+        let id = self.inner.id;
+        self.inner
+            .world
+            .commands
+            .lock()
+            .unwrap()
+            .push(Command::Despawn(id));
+    }
+}
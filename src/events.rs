@@ -0,0 +1,249 @@
+use std::{
+    any::Any,
+    future::Future,
+    marker::PhantomData,
+    mem,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    entities::fetch::Access,
+    resources::{self, Resource, ResourceRef},
+    systems::SystemParam,
+    world::World,
+};
+
+/// Swaps the buffers of the events of one type, see [`World::update_events`].
+pub(crate) type EventUpdater =
+    for<'a> fn(&'a World) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+/// The events of type `T` sent in a world, stored as a resource.
+///
+/// Events are kept in two buffers: the events sent since the last update, and the ones
+/// sent between the two updates before it. Every [`Schedule`](crate::systems::schedule::Schedule)
+/// run starts with an update, which drops the older buffer, so an event can be read
+/// until the end of the run after the one it was sent in. Systems usually send and
+/// read events through [`EventWriter`] and [`EventReader`] rather than using this
+/// resource directly. It is added with [`World::add_event`].
+pub struct Events<T> {
+    // sent since the last update, along with the change tick they were sent at
+    current: Vec<(u64, T)>,
+    // sent between the two previous updates
+    previous: Vec<(u64, T)>,
+    change_tick: Arc<AtomicU64>,
+}
+/// Events are added with [`World::add_event`] rather than registered, so they all share
+/// one name.
+impl<T: Any + Send + Sync> Resource for Events<T> {
+    const NAME: &'static str = "Events";
+}
+
+impl<T> Events<T> {
+    pub(crate) fn new(change_tick: Arc<AtomicU64>) -> Self {
+        Self {
+            current: Vec::new(),
+            previous: Vec::new(),
+            change_tick,
+        }
+    }
+
+    /// Sends `event`, to be read by every [`EventReader`] of this type.
+    pub fn send(&mut self, event: T) {
+        let tick = self.change_tick.load(Ordering::Acquire);
+        self.current.push((tick, event));
+    }
+
+    /// Returns every event that hasn't been dropped yet, from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.previous
+            .iter()
+            .chain(&self.current)
+            .map(|(_, event)| event)
+    }
+
+    /// Returns the number of events that haven't been dropped yet.
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    /// Returns whether every event has been dropped.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the events sent after the change tick `tick`, from oldest to newest.
+    fn iter_since(&self, tick: u64) -> impl Iterator<Item = &T> {
+        self.previous
+            .iter()
+            .chain(&self.current)
+            .filter(move |(sent, _)| *sent > tick)
+            .map(|(_, event)| event)
+    }
+
+    /// Drops the older buffer, and starts a new one.
+    fn update(&mut self) {
+        self.previous = mem::take(&mut self.current);
+    }
+}
+
+/// Updates the events of type `T` in `world`.
+pub(crate) fn update<T: Any + Send + Sync>(
+    world: &World,
+) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+    Box::pin(async move {
+        if let Ok(mut events) = world.get_resource_mut::<Events<T>>().await {
+            events.update();
+        }
+    })
+}
+
+/// A system parameter for sending events of type `T`.
+/// It locks the [`Events`] resource for every event sent, and adds the resource if
+/// the world doesn't have it yet.
+/// ```rust
+/// use jest::{
+///     world::World,
+///     events::{EventReader, EventWriter},
+///     resources::{ResMut, Resource},
+///     systems::schedule::Schedule,
+/// };
+///
+/// struct Damage(u32);
+/// #[derive(Resource)]
+/// struct Health(u32);
+///
+/// async fn attack(mut damage: EventWriter<Damage>) {
+///     damage.send(Damage(10)).await;
+/// }
+///
+/// async fn take_damage(mut damage: EventReader<Damage>, mut health: ResMut<Health>) {
+///     let damage = damage.read().await;
+///     let mut health = health.get_mut().await.unwrap();
+///     for event in damage.iter() {
+///         health.0 -= event.0;
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     world.insert_resource(Health(100)).await;
+///
+///     let mut schedule = Schedule::new();
+///     schedule.add_system(attack);
+///     schedule.add_system(take_damage);
+///     schedule.run(&world).await;
+///     schedule.run(&world).await;
+///
+///     // every event was only read once
+///     assert_eq!(world.get_resource::<Health>().await.unwrap().0, 80);
+/// }
+/// ```
+pub struct EventWriter<T> {
+    world: Arc<World>,
+    _marker: PhantomData<fn() -> T>,
+}
+impl<T: Any + Send + Sync> EventWriter<T> {
+    /// Sends `event`, to be read by every [`EventReader`] of this type.
+    pub async fn send(&mut self, event: T) {
+        if let Ok(mut events) = self.world.get_resource_mut::<Events<T>>().await {
+            events.send(event);
+            return;
+        }
+        self.world.add_event::<T>().await;
+        self.world
+            .get_resource_mut::<Events<T>>()
+            .await
+            .unwrap()
+            .send(event);
+    }
+}
+/// Writing events accesses the [`Events`] resource mutably.
+impl<T: Any + Send + Sync> SystemParam for EventWriter<T> {
+    fn access(access: &mut Vec<Access>) {
+        resources::access::<Events<T>>(access, true);
+    }
+
+    fn fetch(
+        world: &Arc<World>,
+        _last_run: u64,
+        _this_run: u64,
+    ) -> impl Future<Output = Self> + Send {
+        let writer = EventWriter {
+            world: world.clone(),
+            _marker: PhantomData,
+        };
+        async { writer }
+    }
+}
+
+/// A system parameter for reading events of type `T`. Every reader has its own cursor:
+/// it yields the events sent since its system last ran, so each event is read once by
+/// every system reading it, as long as the system runs as part of every
+/// [`Schedule`](crate::systems::schedule::Schedule) run. See [`EventWriter`] for an
+/// example.
+pub struct EventReader<T> {
+    world: Arc<World>,
+    last_run: u64,
+    _marker: PhantomData<fn() -> T>,
+}
+impl<T: Any + Send + Sync> EventReader<T> {
+    /// Read-locks the [`Events`] resource, returning the events that are new to this
+    /// reader. Nothing is returned if the world doesn't have the resource.
+    pub async fn read(&mut self) -> ReadEvents<'_, T> {
+        ReadEvents {
+            events: self.world.get_resource().await.ok(),
+            last_run: self.last_run,
+        }
+    }
+}
+/// Reading events accesses the [`Events`] resource immutably.
+impl<T: Any + Send + Sync> SystemParam for EventReader<T> {
+    fn access(access: &mut Vec<Access>) {
+        resources::access::<Events<T>>(access, false);
+    }
+
+    fn fetch(
+        world: &Arc<World>,
+        last_run: u64,
+        _this_run: u64,
+    ) -> impl Future<Output = Self> + Send {
+        let reader = EventReader {
+            world: world.clone(),
+            last_run,
+            _marker: PhantomData,
+        };
+        async { reader }
+    }
+}
+
+/// The events read by an [`EventReader`].
+///
+/// Beware that holding this will block sending events of this type.
+/// Be sure to drop it as soon as you're done with it.
+pub struct ReadEvents<'a, T> {
+    events: Option<ResourceRef<'a, Events<T>>>,
+    last_run: u64,
+}
+impl<T> ReadEvents<'_, T> {
+    /// Returns the events, from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.events
+            .iter()
+            .flat_map(|events| events.iter_since(self.last_run))
+    }
+
+    /// Returns the number of events.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns whether there are no events.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{entities::EntityId, events::EventWriter, query::Query};
+
+/// A function deciding whether a [`StateMachine`] may go from the first state to the
+/// second.
+pub type Guard<S> = fn(from: &S, to: &S) -> bool;
+
+/// A component holding the state of a finite state machine, for AI and character
+/// controllers. States are any comparable type, such as an enum or string IDs.
+///
+/// Transitions are requested with [`transition`](StateMachine::transition) and applied
+/// by the [`update_state_machines`] system, which checks them against the machine's
+/// guards and sends a [`StateExited`] and a [`StateEntered`] event for each one made.
+/// ```rust
+/// use jest::{
+///     world::World,
+///     entities::builder::EntityBuilder,
+///     events::Events,
+///     fsm::{update_state_machines, StateEntered, StateMachine},
+///     systems::schedule::Schedule,
+/// };
+///
+/// #[derive(Clone, Debug, PartialEq)]
+/// enum Sentry {
+///     Idle,
+///     Chasing,
+///     Attacking,
+/// }
+///
+/// // sentries must chase before they can attack
+/// fn chases_first(from: &Sentry, to: &Sentry) -> bool {
+///     *to != Sentry::Attacking || *from == Sentry::Chasing
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     let mut builder = EntityBuilder::new();
+///     builder.add(StateMachine::new(Sentry::Idle).with_guard(chases_first)).unwrap();
+///     let sentry = builder.build(&world).await;
+///
+///     let mut schedule = Schedule::new();
+///     schedule.add_system(update_state_machines::<Sentry>);
+///     for next in [Sentry::Attacking, Sentry::Chasing] {
+///         let mut entity = world.get_mut(sentry).await.unwrap();
+///         entity.get_mut::<StateMachine<Sentry>>().unwrap().transition(next);
+///         drop(entity);
+///         schedule.run(&world).await;
+///     }
+///
+///     let entity = world.get(sentry).await.unwrap();
+///     assert_eq!(*entity.get::<StateMachine<Sentry>>().unwrap().state(), Sentry::Chasing);
+///     drop(entity);
+///     let events = world.get_resource::<Events<StateEntered<Sentry>>>().await.unwrap();
+///     let entered: Vec<_> = events.iter().map(|event| event.state.clone()).collect();
+///     assert_eq!(entered, [Sentry::Chasing]);
+/// }
+/// ```
+pub struct StateMachine<S> {
+    state: S,
+    next: Option<S>,
+    guards: Vec<Guard<S>>,
+}
+impl<S: Clone + PartialEq + Send + Sync + 'static> StateMachine<S> {
+    /// Creates a machine in the state `initial`, without any guards.
+    pub fn new(initial: S) -> Self {
+        Self {
+            state: initial,
+            next: None,
+            guards: Vec::new(),
+        }
+    }
+
+    /// Adds a guard, which every transition must pass.
+    pub fn with_guard(mut self, guard: Guard<S>) -> Self {
+        self.guards.push(guard);
+        self
+    }
+
+    /// Returns the current state.
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Returns the state requested with [`transition`](StateMachine::transition), if it
+    /// hasn't been applied yet.
+    pub fn next(&self) -> Option<&S> {
+        self.next.as_ref()
+    }
+
+    /// Requests a transition to `state`, replacing any earlier request. It is applied the
+    /// next time [`update_state_machines`] runs, if every guard allows it.
+    pub fn transition(&mut self, state: S) {
+        self.next = Some(state);
+    }
+
+    /// Applies the requested transition if the guards allow it, returning the previous
+    /// state.
+    fn apply(&mut self) -> Option<S> {
+        let next = self.next.take()?;
+        if next == self.state || !self.guards.iter().all(|guard| guard(&self.state, &next)) {
+            return None;
+        }
+        Some(std::mem::replace(&mut self.state, next))
+    }
+}
+
+/// Sent by [`update_state_machines`] when a machine leaves a state.
+pub struct StateExited<S> {
+    /// The entity the machine belongs to.
+    pub entity: EntityId,
+    /// The state left.
+    pub state: S,
+}
+
+/// Sent by [`update_state_machines`] when a machine enters a state, right after the
+/// [`StateExited`] event of the state it left.
+pub struct StateEntered<S> {
+    /// The entity the machine belongs to.
+    pub entity: EntityId,
+    /// The state entered.
+    pub state: S,
+}
+
+/// A system applying the requested transitions of every [`StateMachine`] with states of
+/// type `S`. Requests that a guard rejects, or to the current state, are dropped.
+pub async fn update_state_machines<S: Clone + PartialEq + Send + Sync + 'static>(
+    mut query: Query<&mut StateMachine<S>>,
+    mut exited: EventWriter<StateExited<S>>,
+    mut entered: EventWriter<StateEntered<S>>,
+) {
+    let mut iter = query.iter().await;
+    while let Some((entity, machine)) = iter.next().await {
+        if let Some(state) = machine.apply() {
+            exited.send(StateExited { entity, state }).await;
+            let state = machine.state.clone();
+            entered.send(StateEntered { entity, state }).await;
+        }
+    }
+}
@@ -0,0 +1,281 @@
+#[cfg(feature = "serde")]
+use std::collections::HashMap;
+use std::{
+    any::{Any, TypeId},
+    error::Error,
+    fmt::{self, Display, Formatter},
+    ops::Deref,
+};
+
+use tokio::sync::RwLock;
+
+use crate::entities::{component::Component, map::EntityMap, Entity, EntityId, EntityMut};
+
+/// The parent of an entity. It is added and removed by the world along with the
+/// matching [`Children`], see [`EntityMut::add_child`], and can't be added or removed
+/// by hand.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Parent(EntityId);
+impl Parent {
+    /// Returns the ID of the parent entity.
+    pub fn get(&self) -> EntityId {
+        self.0
+    }
+}
+
+/// The children of an entity, in the order they were added. It is added and removed by
+/// the world along with the matching [`Parent`]s, see [`EntityMut::add_child`], and is
+/// never empty. Like [`Parent`], it can't be added or removed by hand. This type
+/// implements `Deref` to a slice of the children's IDs.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Children(Vec<EntityId>);
+impl Deref for Children {
+    type Target = [EntityId];
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Parent {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Parent {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        EntityId::deserialize(deserializer).map(Parent)
+    }
+}
+#[cfg(feature = "serde")]
+impl serde::Serialize for Children {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Children {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let children = Vec::deserialize(deserializer)?;
+        if children.is_empty() {
+            return Err(serde::de::Error::invalid_length(0, &"at least one child"));
+        }
+        Ok(Children(children))
+    }
+}
+
+/// Error type returned from [`EntityMut::add_child`]
+#[derive(Debug)]
+pub enum HierarchyError {
+    /// The child doesn't exist in the world.
+    NoSuchEntity(EntityId),
+    /// The child is the parent itself, or one of its ancestors.
+    Cycle(EntityId),
+}
+impl Display for HierarchyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            HierarchyError::NoSuchEntity(id) => write!(f, "entity {id} doesn't exist"),
+            HierarchyError::Cycle(id) => {
+                write!(f, "entity {id} can't be a descendant of itself")
+            }
+        }
+    }
+}
+impl Error for HierarchyError {}
+
+impl EntityMut<'_> {
+    /// Makes the entity specified by `child` a child of this entity, detaching it from its
+    /// previous parent if it had one. Returns [`HierarchyError`] if `child` doesn't exist,
+    /// or if it is this entity or one of its ancestors.
+    ///
+    /// The child, its previous parent and this entity's ancestors are locked while this
+    /// runs, so beware of holding a reference to any of them at the same time.
+    /// ```rust
+    /// use jest::{world::World, entities::builder::EntityBuilder, hierarchy::{Children, Parent}};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///     let tank = EntityBuilder::new().build(&world).await;
+    ///     let turret = EntityBuilder::new().build(&world).await;
+    ///
+    ///     let mut entity = world.get_mut(tank).await.unwrap();
+    ///     entity.add_child(turret).await.unwrap();
+    ///     assert_eq!(&**entity.get::<Children>().unwrap(), [turret]);
+    ///     drop(entity);
+    ///
+    ///     let entity = world.get(turret).await.unwrap();
+    ///     assert_eq!(entity.get::<Parent>().unwrap().get(), tank);
+    /// }
+    /// ```
+    pub async fn add_child(&mut self, child: EntityId) -> Result<(), HierarchyError> {
+        let world = self.inner.world.clone();
+        if child == self.id {
+            return Err(HierarchyError::Cycle(child));
+        }
+        // SAFETY: the world is read-locked for as long as `self` lives
+        let entity = |id| unsafe { world.entity(id) }.ok_or(HierarchyError::NoSuchEntity(id));
+        let child_lock = entity(child)?;
+        let mut ancestor = self.get::<Parent>().map(Parent::get);
+        while let Some(id) = ancestor {
+            if id == child {
+                return Err(HierarchyError::Cycle(child));
+            }
+            ancestor = entity(id)?.read().await.get::<Parent>().map(Parent::get);
+        }
+
+        let mut child_entity = child_lock.write().await;
+        match child_entity.get::<Parent>().map(Parent::get) {
+            Some(parent) if parent == self.id => return Ok(()),
+            Some(parent) => remove_from_children(&mut *entity(parent)?.write().await, child),
+            None => {}
+        }
+        child_entity.replace_linked(Parent(self.id));
+        match self.get_mut::<Children>() {
+            Some(children) => children.0.push(child),
+            None => {
+                self.replace_linked(Children(vec![child]));
+            }
+        }
+        Ok(())
+    }
+
+    /// Detaches the entity specified by `child` from this entity, returning whether it was
+    /// a child of this entity. The child is locked while this runs.
+    pub async fn remove_child(&mut self, child: EntityId) -> bool {
+        if !self
+            .get::<Children>()
+            .is_some_and(|children| children.contains(&child))
+        {
+            return false;
+        }
+        remove_from_children(&mut self.inner, child);
+        let world = self.inner.world.clone();
+        // SAFETY: the world is read-locked for as long as `self` lives
+        if let Some(child) = unsafe { world.entity(child) } {
+            take::<Parent>(&mut *child.write().await);
+        }
+        true
+    }
+}
+
+/// Removes `child` from the children of `parent`, removing the [`Children`] component
+/// once it is empty.
+fn remove_from_children(parent: &mut Entity, child: EntityId) {
+    let Some(children) = parent.get_mut::<Children>() else {
+        return;
+    };
+    children.0.retain(|other| *other != child);
+    if children.0.is_empty() {
+        take::<Children>(parent);
+    }
+}
+
+/// Returns whether `id` is the type ID of [`Parent`] or [`Children`], which are only
+/// removed along with the other side of their link.
+pub(crate) fn is_link(id: TypeId) -> bool {
+    id == TypeId::of::<Parent>() || id == TypeId::of::<Children>()
+}
+
+/// Panics if `id` is the type ID of [`Parent`] or [`Children`], which are only added
+/// along with the other side of their link. `name` is the name of the type.
+pub(crate) fn assert_not_link(id: TypeId, name: &str) {
+    assert!(
+        !is_link(id),
+        "`{name}` is part of the hierarchy and can't be added by hand, use `EntityMut::add_child` instead"
+    );
+}
+
+/// Checks that the [`Parent`]s and [`Children`] of `entities`, which were deserialized
+/// rather than linked by the world, match each other and form a tree.
+#[cfg(feature = "serde")]
+pub(crate) fn check(entities: &mut EntityMap<RwLock<Entity>>) -> Result<(), String> {
+    let links: HashMap<_, _> = entities
+        .iter_mut()
+        .map(|(id, entity)| {
+            let entity = entity.get_mut();
+            let parent = entity.get::<Parent>().map(Parent::get);
+            let children = entity
+                .get::<Children>()
+                .map_or_else(Vec::new, |c| c.to_vec());
+            (id, (parent, children))
+        })
+        .collect();
+    for (id, (parent, children)) in &links {
+        if let Some(parent) = parent {
+            if !links
+                .get(parent)
+                .is_some_and(|(_, siblings)| siblings.contains(id))
+            {
+                return Err(format!(
+                    "entity {id} isn't among the children of its parent {parent}"
+                ));
+            }
+        }
+        for (i, child) in children.iter().enumerate() {
+            if children[..i].contains(child)
+                || links.get(child).map(|(parent, _)| *parent) != Some(Some(*id))
+            {
+                return Err(format!(
+                    "entity {child} is among the children of {id}, but isn't its child"
+                ));
+            }
+        }
+    }
+    // links that match can still go around in a circle
+    for id in links.keys() {
+        let mut ancestor = links[id].0;
+        for _ in 0..links.len() {
+            let Some(parent) = ancestor else {
+                break;
+            };
+            if parent == *id {
+                return Err(format!("entity {id} is a descendant of itself"));
+            }
+            ancestor = links[&parent].0;
+        }
+    }
+    Ok(())
+}
+
+/// Removes the [`Parent`] or [`Children`] of `entity`, see [`is_link`].
+fn take<T: Component>(entity: &mut Entity) -> Option<T> {
+    let component: Box<dyn Any> = entity.remove_linked(TypeId::of::<T>())?;
+    Some(*component.downcast::<T>().unwrap())
+}
+
+/// Takes `entity`, which was just removed from `entities`, out of its hierarchy: it is
+/// removed from the children of its parent, and its children lose their parent.
+pub(crate) fn unlink(entities: &mut EntityMap<RwLock<Entity>>, entity: &mut Entity) {
+    if let Some(parent) = take::<Parent>(entity) {
+        if let Some(parent) = entities.get_mut(parent.0) {
+            remove_from_children(parent.get_mut(), entity.id);
+        }
+    }
+    for child in take::<Children>(entity)
+        .into_iter()
+        .flat_map(|children| children.0)
+    {
+        if let Some(child) = entities.get_mut(child) {
+            take::<Parent>(child.get_mut());
+        }
+    }
+}
+
+/// Returns the IDs of `id` and all of its descendants, parents before their children.
+pub(crate) fn subtree(entities: &mut EntityMap<RwLock<Entity>>, id: EntityId) -> Vec<EntityId> {
+    let mut subtree = vec![id];
+    let mut i = 0;
+    while let Some(id) = subtree.get(i).copied() {
+        if let Some(children) = entities
+            .get_mut(id)
+            .and_then(|entity| entity.get_mut().get::<Children>())
+        {
+            subtree.extend_from_slice(children);
+        }
+        i += 1;
+    }
+    subtree
+}
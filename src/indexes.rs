@@ -0,0 +1,150 @@
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    sync::Mutex,
+};
+
+use crate::entities::{component::Component, EntityId};
+
+/// A component whose entities can be looked up by a key computed from its value, such as
+/// a faction ID or a grid cell. Once the type is registered with
+/// [`World::register_index`](crate::world::World::register_index), the world keeps an
+/// index from keys to entities up to date as components of this type are added,
+/// changed and removed, and [`World::lookup`](crate::world::World::lookup) returns the
+/// entities with a given key without scanning the others.
+/// ```rust
+/// use jest::{world::World, entities::builder::EntityBuilder, indexes::Indexed};
+///
+/// struct Faction {
+///     id: u32,
+///     reputation: i32,
+/// }
+/// impl Indexed for Faction {
+///     type Key = u32;
+///
+///     fn key(&self) -> u32 {
+///         self.id
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     world.register_index::<Faction>();
+///     let mut builder = EntityBuilder::new();
+///     builder.add(Faction { id: 1, reputation: 0 }).unwrap();
+///     let guard = builder.build(&world).await;
+///     assert_eq!(world.lookup::<Faction>(&1).await, [guard]);
+///
+///     // the guard defects
+///     let mut entity = world.get_mut(guard).await.unwrap();
+///     entity.get_mut::<Faction>().unwrap().id = 2;
+///     drop(entity);
+///     assert!(world.lookup::<Faction>(&1).await.is_empty());
+///     assert_eq!(world.lookup::<Faction>(&2).await, [guard]);
+/// }
+/// ```
+pub trait Indexed: Component {
+    /// The type of the key entities are looked up by.
+    type Key: Eq + Hash + Clone + Send + Sync + 'static;
+
+    /// Returns the key this component is indexed under.
+    fn key(&self) -> Self::Key;
+}
+
+/// The entities with a component of one [`Indexed`] type in a world, by key.
+pub(crate) struct ValueIndex {
+    keys: Box<dyn Keys>,
+    // the entities whose component may have changed since they were last indexed
+    dirty: Mutex<HashSet<EntityId>>,
+}
+impl ValueIndex {
+    pub(crate) fn of<T: Indexed>() -> Self {
+        Self {
+            keys: Box::new(KeyMap::<T> {
+                keys: HashMap::new(),
+                entities: HashMap::new(),
+            }),
+            dirty: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Records that `entity` now has `component`, replacing its previous key.
+    pub(crate) fn link(&mut self, entity: EntityId, component: &dyn Component) {
+        self.dirty.get_mut().unwrap().remove(&entity);
+        self.keys.link(entity, component);
+    }
+
+    /// Records that `entity` no longer has a component of this type.
+    pub(crate) fn unlink(&mut self, entity: EntityId) {
+        self.dirty.get_mut().unwrap().remove(&entity);
+        self.keys.unlink(entity);
+    }
+
+    /// Records that the component of `entity` may have changed, so that it is indexed
+    /// again before the next lookup.
+    pub(crate) fn mark(&self, entity: EntityId) {
+        self.dirty.lock().unwrap().insert(entity);
+    }
+
+    /// Returns the entities that were [marked](ValueIndex::mark) since they were last
+    /// indexed.
+    pub(crate) fn dirty(&mut self) -> Vec<EntityId> {
+        self.dirty.get_mut().unwrap().iter().copied().collect()
+    }
+
+    /// Returns the entities indexed under `key`.
+    pub(crate) fn entities<T: Indexed>(&self, key: &T::Key) -> &[EntityId] {
+        let keys: &dyn Any = self.keys.as_any();
+        keys.downcast_ref::<KeyMap<T>>()
+            .unwrap()
+            .entities
+            .get(key)
+            .map_or(&[], Vec::as_slice)
+    }
+}
+
+/// The keys of the components of one [`Indexed`] type, with the type erased.
+trait Keys: Send + Sync {
+    fn link(&mut self, entity: EntityId, component: &dyn Component);
+
+    fn unlink(&mut self, entity: EntityId);
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+struct KeyMap<T: Indexed> {
+    // the key of every entity
+    keys: HashMap<EntityId, T::Key>,
+    // the entities with every key
+    entities: HashMap<T::Key, Vec<EntityId>>,
+}
+impl<T: Indexed> Keys for KeyMap<T> {
+    fn link(&mut self, entity: EntityId, component: &dyn Component) {
+        let component: &dyn Any = component;
+        let key = component.downcast_ref::<T>().unwrap().key();
+        if self.keys.get(&entity) == Some(&key) {
+            return;
+        }
+        self.unlink(entity);
+        self.keys.insert(entity, key.clone());
+        self.entities.entry(key).or_default().push(entity);
+    }
+
+    fn unlink(&mut self, entity: EntityId) {
+        let Some(key) = self.keys.remove(&entity) else {
+            return;
+        };
+        if let Some(entities) = self.entities.get_mut(&key) {
+            entities.retain(|other| *other != entity);
+            if entities.is_empty() {
+                self.entities.remove(&key);
+            }
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
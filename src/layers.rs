@@ -0,0 +1,75 @@
+use std::ops::{BitAnd, BitOr};
+
+/// A component placing its entity in up to 32 layers, such as players, enemies or
+/// projectiles, each being one bit of the mask. Queries can be restricted to entities in
+/// some layers with [`Query::in_layers`](crate::query::Query::in_layers), without a
+/// marker type for every category.
+/// ```rust
+/// use jest::{world::World, entities::builder::EntityBuilder, layers::Layers};
+///
+/// const PLAYER: Layers = Layers::layer(0);
+/// const ENEMY: Layers = Layers::layer(1);
+/// const PROJECTILE: Layers = Layers::layer(2);
+///
+/// struct Health(u32);
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     for layers in [PLAYER, ENEMY, ENEMY | PROJECTILE] {
+///         let mut builder = EntityBuilder::new();
+///         builder.add_bundle((Health(10), layers)).unwrap();
+///         builder.build(&world).await;
+///     }
+///
+///     let mut query = world.query::<&Health>().in_layers(PLAYER | PROJECTILE);
+///     let mut iter = query.iter().await;
+///     let mut matched = 0;
+///     while iter.next().await.is_some() {
+///         matched += 1;
+///     }
+///     assert_eq!(matched, 2);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Layers(pub u32);
+impl Layers {
+    /// No layer at all.
+    pub const NONE: Layers = Layers(0);
+    /// Every layer.
+    pub const ALL: Layers = Layers(u32::MAX);
+
+    /// Returns the mask of the single layer `index`.
+    ///
+    /// # Panics
+    /// Panics if `index` is 32 or more.
+    pub const fn layer(index: u32) -> Layers {
+        assert!(index < u32::BITS, "there are only 32 layers");
+        Layers(1 << index)
+    }
+
+    /// Returns whether any layer is in both masks.
+    pub const fn intersects(self, other: Layers) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Returns whether every layer of `other` is also in this mask.
+    pub const fn contains(self, other: Layers) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+impl BitOr for Layers {
+    type Output = Layers;
+
+    fn bitor(self, other: Layers) -> Layers {
+        Layers(self.0 | other.0)
+    }
+}
+impl BitAnd for Layers {
+    type Output = Layers;
+
+    fn bitand(self, other: Layers) -> Layers {
+        Layers(self.0 & other.0)
+    }
+}
@@ -2,7 +2,46 @@
 
 //! List of modules in the library
 
+/// Archetype-based component storage
+mod archetype;
+/// Deferred structural changes to a world
+pub mod commands;
 /// Entities
 pub mod entities;
+/// Messages sent between systems
+pub mod events;
+/// Per-entity finite state machines
+pub mod fsm;
+/// Parent/child relationships between entities
+pub mod hierarchy;
+/// Entities looked up by the value of a component
+pub mod indexes;
+/// Bitmask categories of entities, for narrowing down queries
+pub mod layers;
+/// Re-exports of the commonly used types, so they can be imported all at once
+pub mod prelude;
+/// Queries over the entities of a world
+pub mod query;
+/// Typed relations between entities, indexed in both directions
+pub mod relations;
+/// Detecting components removed from entities
+pub mod removal;
+/// Global data stored in a world, outside of any entity
+pub mod resources;
+/// Deterministic random numbers
+pub mod rng;
+/// Entities described in RON text, spawned as a whole
+#[cfg(feature = "serde")]
+pub mod scene;
+/// Saving and restoring worlds with serde
+#[cfg(feature = "serde")]
+mod serialization;
+/// Systems, and schedules for running them
+pub mod systems;
+/// Changes to several entities applied all at once, or not at all
+pub mod transaction;
 /// World
 pub mod world;
+
+/// The error returned by fallible systems, see [`IntoSystem`](systems::IntoSystem).
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
@@ -0,0 +1,31 @@
+//! ```rust
+//! use jest::prelude::*;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let world = World::new();
+//!     let entity_id: EntityId = EntityBuilder::new().build(&world).await;
+//!     assert!(world.get(entity_id).await.is_some());
+//! }
+//! ```
+
+pub use crate::{
+    commands::Commands,
+    entities::{
+        builder::EntityBuilder,
+        bundle::Bundle,
+        component::{Component, ComponentOptions, StorageType},
+        fetch::Ref,
+        Entity, EntityId,
+    },
+    events::{EventReader, EventWriter, Events},
+    hierarchy::{Children, Parent},
+    indexes::Indexed,
+    layers::Layers,
+    query::{Added, Changed, Or, Query, With, Without},
+    relations::Relation,
+    removal::RemovedComponents,
+    resources::{Res, ResMut, Resource},
+    systems::{schedule::Schedule, IntoSystem, System},
+    world::World,
+};
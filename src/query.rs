@@ -0,0 +1,529 @@
+use std::{any::TypeId, future::Future, marker::PhantomData, sync::Arc, vec};
+
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{
+    entities::{
+        component::{Component, StorageType},
+        fetch::{self, Fetch},
+        map::EntityMap,
+        Entity, EntityId,
+    },
+    layers::Layers,
+    systems::{self, Claim, SystemParam},
+    world::World,
+};
+
+/// A query over every entity in a world that has a given set of components.
+///
+/// `Q` is a component reference (`&T` or `&mut T`) or a tuple of them, in the
+/// same form as [`Entity::get_many`]. Create one with [`World::query`], then
+/// call [`iter`](Query::iter) to walk the matching entities. `F` is a [`QueryFilter`]
+/// further restricting which entities are matched, without borrowing any components:
+/// ```rust
+/// use jest::{world::World, entities::builder::EntityBuilder};
+///
+/// struct Position(f32);
+/// struct Velocity(f32);
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     for velocity in [1.0, 2.0] {
+///         let mut builder = EntityBuilder::new();
+///         builder.add_bundle((Position(0.0), Velocity(velocity))).unwrap();
+///         builder.build(&world).await;
+///     }
+///     // not matched, it has no velocity
+///     let mut builder = EntityBuilder::new();
+///     builder.add(Position(0.0)).unwrap();
+///     builder.build(&world).await;
+///
+///     let mut query = world.query::<(&mut Position, &Velocity)>();
+///     let mut iter = query.iter().await;
+///     let mut moved = 0;
+///     while let Some((_id, (position, velocity))) = iter.next().await {
+///         position.0 += velocity.0;
+///         moved += 1;
+///     }
+///     assert_eq!(moved, 2);
+/// }
+/// ```
+pub struct Query<Q: Fetch, F: QueryFilter = ()> {
+    world: Arc<World>,
+    last_run: u64,
+    this_run: u64,
+    // the masks given to `in_layers`, which an entity's layers must each intersect
+    layers: Vec<Layers>,
+    _marker: PhantomData<fn() -> (Q, F)>,
+}
+impl<Q: Fetch, F: QueryFilter> Query<Q, F> {
+    pub(crate) fn new(world: Arc<World>, last_run: u64, this_run: u64) -> Self {
+        Self {
+            world,
+            last_run,
+            this_run,
+            layers: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Restricts the query to entities that also match the filter `G`.
+    /// ```rust
+    /// use jest::{
+    ///     world::World,
+    ///     entities::builder::EntityBuilder,
+    ///     query::{With, Without},
+    /// };
+    ///
+    /// struct Position(f32);
+    /// struct Enemy;
+    /// struct Dead;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///     let mut builder = EntityBuilder::new();
+    ///     builder.add_bundle((Position(1.0), Enemy)).unwrap();
+    ///     let alive = builder.build(&world).await;
+    ///     let mut builder = EntityBuilder::new();
+    ///     builder.add_bundle((Position(2.0), Enemy, Dead)).unwrap();
+    ///     builder.build(&world).await;
+    ///
+    ///     let mut query = world
+    ///         .query::<&Position>()
+    ///         .filter::<(With<Enemy>, Without<Dead>)>();
+    ///     let mut iter = query.iter().await;
+    ///     assert_eq!(iter.next().await.unwrap().0, alive);
+    ///     assert!(iter.next().await.is_none());
+    /// }
+    /// ```
+    pub fn filter<G: QueryFilter>(self) -> Query<Q, (F, G)> {
+        Query {
+            world: self.world,
+            last_run: self.last_run,
+            this_run: self.this_run,
+            layers: self.layers,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Restricts the query to entities with a [`Layers`] component in at least one of
+    /// the layers of `mask`. Calling it again further restricts the query, to entities
+    /// in a layer of each mask.
+    ///
+    /// Unlike a filter type, the mask isn't known when systems are scheduled, so a
+    /// system taking this query may run alongside one changing the layers of entities.
+    pub fn in_layers(mut self, mask: Layers) -> Self {
+        self.layers.push(mask);
+        self
+    }
+
+    /// Starts iterating over the matching entities.
+    ///
+    /// The iterator holds a read lock on the world for as long as it lives, so
+    /// entities can't be added to or removed from the world until it is dropped.
+    /// Only the entities stored in archetypes with the requested components, or in the
+    /// smallest sparse set of the requested components, are visited;
+    /// an entity that gains or loses components in another task during the iteration may
+    /// be skipped.
+    ///
+    /// # Panics
+    /// Panics if `Q` requests the same component type mutably more than once, or
+    /// both mutably and immutably. Within a system, the iterator also panics when it
+    /// reaches an entity that another query of the system is iterating at, see
+    /// [`IntoSystem::into_system`](crate::systems::IntoSystem::into_system).
+    pub async fn iter(&mut self) -> QueryIter<'_, Q, F> {
+        let mut access = Vec::new();
+        Q::access(&mut access);
+        fetch::assert_disjoint(&access, "the same fetch");
+        let mut ids: Vec<_> = access.iter().map(|access| access.id).collect();
+        if !self.layers.is_empty() {
+            ids.push(TypeId::of::<Layers>());
+        }
+        let (outer, entities) = self.world.entities().await;
+        let (sparse, table): (Vec<_>, Vec<_>) = ids
+            .into_iter()
+            .partition(|id| self.world.storage(*id) == StorageType::SparseSet);
+        let candidates: Vec<_> = if sparse.is_empty() {
+            self.world
+                .archetypes_with(&table)
+                .iter()
+                .flat_map(|archetype| archetype.entities())
+                .collect()
+        } else {
+            // every match is in every sparse set, so only the smallest one is visited
+            sparse
+                .iter()
+                .map(|id| {
+                    self.world
+                        .find_sparse_set(*id)
+                        .map_or_else(Vec::new, |set| set.entities())
+                })
+                .min_by_key(Vec::len)
+                .unwrap()
+        };
+        QueryIter {
+            _outer: outer,
+            entities,
+            candidates: candidates.into_iter(),
+            current: None,
+            write: access.iter().any(|access| access.mutable),
+            layers: &self.layers,
+            last_run: self.last_run,
+            this_run: self.this_run,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// A query can be taken by a system. It doesn't lock anything until it is iterated.
+impl<Q: Fetch + 'static, F: QueryFilter + 'static> SystemParam for Query<Q, F> {
+    fn access(access: &mut Vec<fetch::Access>) {
+        Q::access(access);
+        F::access(access);
+    }
+
+    fn fetch(
+        world: &Arc<World>,
+        last_run: u64,
+        this_run: u64,
+    ) -> impl Future<Output = Self> + Send {
+        let query = Query::new(world.clone(), last_run, this_run);
+        async { query }
+    }
+}
+
+/// Creates a [`Query`] from named component references and filters, without spelling
+/// out its type. The names only label the components, which are returned in the same
+/// order, as a tuple even if there is only one. Each filter is `with`, `without`,
+/// `added` or `changed`, followed by a component type:
+/// ```rust
+/// use jest::{world::World, entities::builder::EntityBuilder, query};
+///
+/// struct Position(f32);
+/// struct Velocity(f32);
+/// struct Player;
+/// struct Dead;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     let mut builder = EntityBuilder::new();
+///     builder.add_bundle((Position(0.0), Velocity(1.0), Player)).unwrap();
+///     builder.build(&world).await;
+///     let mut builder = EntityBuilder::new();
+///     builder.add_bundle((Position(0.0), Velocity(1.0), Player, Dead)).unwrap();
+///     builder.build(&world).await;
+///
+///     // the same as `world.query::<(&mut Position, &Velocity)>()
+///     //     .filter::<(With<Player>, Without<Dead>)>()`
+///     let mut query = query!(
+///         world,
+///         (position: &mut Position, velocity: &Velocity),
+///         with Player,
+///         without Dead,
+///     );
+///     let mut iter = query.iter().await;
+///     let mut moved = 0;
+///     while let Some((_id, (position, velocity))) = iter.next().await {
+///         position.0 += velocity.0;
+///         moved += 1;
+///     }
+///     assert_eq!(moved, 1);
+/// }
+/// ```
+#[macro_export]
+macro_rules! query {
+    ($world:expr, ($($name:ident: $fetch:ty),* $(,)?) $(, $kind:ident $filter:ty)* $(,)?) => {
+        $world
+            .query::<($($fetch,)*)>()
+            .filter::<($($crate::__query_filter!($kind $filter),)*)>()
+    };
+}
+
+/// Turns a filter of [`query!`] into its [`QueryFilter`] type.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __query_filter {
+    (with $filter:ty) => {
+        $crate::query::With<$filter>
+    };
+    (without $filter:ty) => {
+        $crate::query::Without<$filter>
+    };
+    (added $filter:ty) => {
+        $crate::query::Added<$filter>
+    };
+    (changed $filter:ty) => {
+        $crate::query::Changed<$filter>
+    };
+    ($kind:ident $filter:ty) => {
+        ::std::compile_error!(::std::concat!(
+            "unknown query filter `",
+            ::std::stringify!($kind),
+            "`, expected `with`, `without`, `added` or `changed`"
+        ))
+    };
+}
+
+/// The lock held on the entity a [`QueryIter`] is currently at.
+// the guards are only held, never read
+#[allow(dead_code)]
+enum EntityGuard<'a> {
+    Read(RwLockReadGuard<'a, Entity>),
+    Write(RwLockWriteGuard<'a, Entity>),
+}
+
+/// An iterator over the entities matched by a [`Query`].
+///
+/// Each entity is locked when the iterator reaches it, for reading or for writing
+/// depending on whether the query borrows any component mutably, and unlocked when
+/// it moves on to the next one.
+pub struct QueryIter<'a, Q: Fetch, F: QueryFilter = ()> {
+    _outer: RwLockReadGuard<'a, ()>,
+    entities: &'a EntityMap<RwLock<Entity>>,
+    // the entities in the matching archetypes when iteration started
+    candidates: vec::IntoIter<EntityId>,
+    // dropped in order, so the entity is unlocked before it is released
+    current: Option<(EntityGuard<'a>, Claim)>,
+    write: bool,
+    layers: &'a [Layers],
+    last_run: u64,
+    this_run: u64,
+    _marker: PhantomData<fn() -> (Q, F)>,
+}
+impl<Q: Fetch, F: QueryFilter> QueryIter<'_, Q, F> {
+    /// Returns the next matching entity's ID along with the requested components,
+    /// or `None` once every entity has been visited.
+    pub async fn next(&mut self) -> Option<(EntityId, Q::Item<'_>)> {
+        self.current = None;
+        for id in self.candidates.by_ref() {
+            let Some(entity) = self.entities.get(id) else {
+                continue;
+            };
+            let claim = systems::claim(id);
+            let (guard, entity) = if self.write {
+                let mut guard = entity.write().await;
+                let entity: *mut Entity = &mut *guard;
+                (EntityGuard::Write(guard), entity)
+            } else {
+                let guard = entity.read().await;
+                let entity = &*guard as *const Entity as *mut Entity;
+                (EntityGuard::Read(guard), entity)
+            };
+            // SAFETY: the entity stays locked until the next call, which requires the
+            // returned references to be gone, and it is only locked for reading if
+            // nothing is borrowed mutably
+            if !F::matches(unsafe { &*entity }, self.last_run)
+                || !in_layers(unsafe { &*entity }, self.layers)
+            {
+                continue;
+            }
+            if let Some(item) = unsafe { Q::fetch(entity, self.last_run, self.this_run) } {
+                self.current = Some((guard, claim));
+                return Some((id, item));
+            }
+        }
+        None
+    }
+}
+
+/// Returns whether the layers of `entity` intersect each of `masks`.
+fn in_layers(entity: &Entity, masks: &[Layers]) -> bool {
+    masks.is_empty()
+        || entity
+            .get::<Layers>()
+            .is_some_and(|layers| masks.iter().all(|mask| layers.intersects(*mask)))
+}
+
+/// A condition on which components an entity has, used to narrow down a [`Query`]
+/// without borrowing the components involved.
+///
+/// Filters are [`With`], [`Without`], [`Added`], [`Changed`] and [`Or`]. A tuple of
+/// filters matches if all of them match, and `()` matches every entity.
+pub trait QueryFilter {
+    /// Pushes a read of every component type the filter checks onto `access`, so that
+    /// systems writing them aren't run at the same time as the querying system.
+    fn access(access: &mut Vec<fetch::Access>);
+
+    /// Returns whether `entity` passes the filter. `last_run` is the
+    /// [change tick](World::change_tick) changes are detected relative to.
+    fn matches(entity: &Entity, last_run: u64) -> bool;
+}
+
+/// Matches entities that have a component of type `T`.
+pub struct With<T>(PhantomData<fn() -> T>);
+impl<T: Component> QueryFilter for With<T> {
+    fn access(access: &mut Vec<fetch::Access>) {
+        fetch::filter_access::<T>(access);
+    }
+
+    fn matches(entity: &Entity, _last_run: u64) -> bool {
+        entity.get::<T>().is_some()
+    }
+}
+
+/// Matches entities that don't have a component of type `T`.
+pub struct Without<T>(PhantomData<fn() -> T>);
+impl<T: Component> QueryFilter for Without<T> {
+    fn access(access: &mut Vec<fetch::Access>) {
+        fetch::filter_access::<T>(access);
+    }
+
+    fn matches(entity: &Entity, _last_run: u64) -> bool {
+        entity.get::<T>().is_none()
+    }
+}
+
+/// Matches entities whose component of type `T` was added since the querying system
+/// last ran, including entities spawned since then.
+/// ```rust
+/// use jest::{
+///     world::World,
+///     entities::builder::EntityBuilder,
+///     query::{Added, Query},
+///     resources::{ResMut, Resource},
+///     systems::schedule::Schedule,
+/// };
+///
+/// struct Enemy;
+/// #[derive(Resource)]
+/// struct Greeted(u32);
+///
+/// async fn greet(mut query: Query<&Enemy, Added<Enemy>>, mut greeted: ResMut<Greeted>) {
+///     let mut iter = query.iter().await;
+///     while iter.next().await.is_some() {
+///         greeted.get_mut().await.unwrap().0 += 1;
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     world.insert_resource(Greeted(0)).await;
+///     let mut schedule = Schedule::new();
+///     schedule.add_system(greet);
+///
+///     for _ in 0..2 {
+///         let mut builder = EntityBuilder::new();
+///         builder.add(Enemy).unwrap();
+///         builder.build(&world).await;
+///         schedule.run(&world).await;
+///     }
+///     // every enemy was only greeted once
+///     assert_eq!(world.get_resource::<Greeted>().await.unwrap().0, 2);
+/// }
+/// ```
+pub struct Added<T>(PhantomData<fn() -> T>);
+impl<T: Component> QueryFilter for Added<T> {
+    fn access(access: &mut Vec<fetch::Access>) {
+        fetch::filter_access::<T>(access);
+    }
+
+    fn matches(entity: &Entity, last_run: u64) -> bool {
+        entity
+            .ticks(TypeId::of::<T>())
+            .is_some_and(|ticks| ticks.added > last_run)
+    }
+}
+
+/// Matches entities whose component of type `T` was added or mutably borrowed since
+/// the querying system last ran. A component counts as changed as soon as a mutable
+/// reference to it is handed out, whether or not it is written to.
+/// ```rust
+/// use jest::{
+///     world::World,
+///     entities::builder::EntityBuilder,
+///     query::{Changed, Query},
+///     resources::{ResMut, Resource},
+///     systems::schedule::Schedule,
+/// };
+///
+/// struct Transform(f32);
+/// #[derive(Resource)]
+/// struct Moves(u32);
+///
+/// async fn count_moves(
+///     mut query: Query<&Transform, Changed<Transform>>,
+///     mut moves: ResMut<Moves>,
+/// ) {
+///     let mut iter = query.iter().await;
+///     while iter.next().await.is_some() {
+///         moves.get_mut().await.unwrap().0 += 1;
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     world.insert_resource(Moves(0)).await;
+///     let mut builder = EntityBuilder::new();
+///     builder.add(Transform(0.0)).unwrap();
+///     let entity_id = builder.build(&world).await;
+///
+///     let mut schedule = Schedule::new();
+///     schedule.add_system(count_moves);
+///     // added components count as changed
+///     schedule.run(&world).await;
+///     schedule.run(&world).await;
+///     assert_eq!(world.get_resource::<Moves>().await.unwrap().0, 1);
+///
+///     world.get_mut(entity_id).await.unwrap().get_mut::<Transform>().unwrap().0 = 1.0;
+///     schedule.run(&world).await;
+///     assert_eq!(world.get_resource::<Moves>().await.unwrap().0, 2);
+/// }
+/// ```
+pub struct Changed<T>(PhantomData<fn() -> T>);
+impl<T: Component> QueryFilter for Changed<T> {
+    fn access(access: &mut Vec<fetch::Access>) {
+        fetch::filter_access::<T>(access);
+    }
+
+    fn matches(entity: &Entity, last_run: u64) -> bool {
+        entity
+            .ticks(TypeId::of::<T>())
+            .is_some_and(|ticks| ticks.changed > last_run)
+    }
+}
+
+/// Matches entities that pass any of the filters in the tuple `T`.
+pub struct Or<T>(PhantomData<fn() -> T>);
+
+macro_rules! impl_query_filter {
+    ($($name:ident),*) => {
+        impl<$($name: QueryFilter),*> QueryFilter for ($($name,)*) {
+            #[allow(unused_variables)]
+            fn access(access: &mut Vec<fetch::Access>) {
+                $($name::access(access);)*
+            }
+
+            #[allow(unused_variables)]
+            fn matches(entity: &Entity, last_run: u64) -> bool {
+                true $(&& $name::matches(entity, last_run))*
+            }
+        }
+
+        impl<$($name: QueryFilter),*> QueryFilter for Or<($($name,)*)> {
+            #[allow(unused_variables)]
+            fn access(access: &mut Vec<fetch::Access>) {
+                $($name::access(access);)*
+            }
+
+            #[allow(unused_variables)]
+            fn matches(entity: &Entity, last_run: u64) -> bool {
+                false $(|| $name::matches(entity, last_run))*
+            }
+        }
+    };
+}
+
+impl_query_filter!();
+impl_query_filter!(A);
+impl_query_filter!(A, B);
+impl_query_filter!(A, B, C);
+impl_query_filter!(A, B, C, D);
+impl_query_filter!(A, B, C, D, E);
+impl_query_filter!(A, B, C, D, E, F);
+impl_query_filter!(A, B, C, D, E, F, G);
+impl_query_filter!(A, B, C, D, E, F, G, H);
@@ -0,0 +1,92 @@
+use std::{any::Any, collections::HashMap};
+
+use crate::entities::{component::Component, EntityId};
+
+/// A component relating its entity, the source, to another entity, the target. Once the
+/// type is registered with [`World::register_relation`](crate::world::World::register_relation),
+/// the world indexes relations of this type in both directions: the target of an entity
+/// is its component, and [`World::relations`](crate::world::World::relations) lists the
+/// sources of a target. When the target is despawned, the relation is removed from all
+/// of its sources.
+///
+/// The target must not be changed in place, through a mutable reference to the
+/// component. Use [`Entity::replace`](crate::entities::Entity::replace) instead.
+/// ```rust
+/// use jest::{
+///     world::World,
+///     entities::{builder::EntityBuilder, EntityId},
+///     relations::Relation,
+/// };
+///
+/// struct Targets(EntityId);
+/// impl Relation for Targets {
+///     fn target(&self) -> EntityId {
+///         self.0
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     world.register_relation::<Targets>();
+///     let player = EntityBuilder::new().build(&world).await;
+///     let mut builder = EntityBuilder::new();
+///     builder.add(Targets(player)).unwrap();
+///     let turret = builder.build(&world).await;
+///
+///     assert_eq!(world.relations::<Targets>(player), [turret]);
+///     world.remove(player).await;
+///     assert!(world.get(turret).await.unwrap().get::<Targets>().is_none());
+/// }
+/// ```
+pub trait Relation: Component {
+    /// Returns the entity this relation points to.
+    fn target(&self) -> EntityId;
+}
+
+/// The relations of one type in a world, indexed in both directions.
+pub(crate) struct RelationIndex {
+    target: fn(&dyn Component) -> EntityId,
+    // the target of every source
+    targets: HashMap<EntityId, EntityId>,
+    // the sources of every target, in the order they were related to it
+    sources: HashMap<EntityId, Vec<EntityId>>,
+}
+impl RelationIndex {
+    pub(crate) fn of<R: Relation>() -> Self {
+        Self {
+            target: |component| {
+                let component: &dyn Any = component;
+                component.downcast_ref::<R>().unwrap().target()
+            },
+            targets: HashMap::new(),
+            sources: HashMap::new(),
+        }
+    }
+
+    /// Records that `source` now has `component`, replacing its previous relation.
+    pub(crate) fn link(&mut self, source: EntityId, component: &dyn Component) {
+        self.unlink(source);
+        let target = (self.target)(component);
+        self.targets.insert(source, target);
+        self.sources.entry(target).or_default().push(source);
+    }
+
+    /// Records that `source` no longer has a relation of this type.
+    pub(crate) fn unlink(&mut self, source: EntityId) {
+        let Some(target) = self.targets.remove(&source) else {
+            return;
+        };
+        if let Some(sources) = self.sources.get_mut(&target) {
+            sources.retain(|other| *other != source);
+            if sources.is_empty() {
+                self.sources.remove(&target);
+            }
+        }
+    }
+
+    /// Returns the sources related to `target`.
+    pub(crate) fn sources(&self, target: EntityId) -> &[EntityId] {
+        self.sources.get(&target).map_or(&[], Vec::as_slice)
+    }
+}
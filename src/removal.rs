@@ -0,0 +1,86 @@
+use std::{any::TypeId, future::Future, marker::PhantomData, sync::Arc};
+
+use crate::{
+    entities::{component::Component, fetch::Access, EntityId},
+    systems::SystemParam,
+    world::World,
+};
+
+/// A system parameter listing the entities a component of type `T` was removed from
+/// since the system last ran, including entities that were despawned with one.
+///
+/// The removed components themselves are gone by then, so this is meant for cleaning
+/// up whatever was kept outside of the world for them, such as a physics body for a
+/// collider. Removals are remembered until every system of a
+/// [`Schedule`](crate::systems::schedule::Schedule) had the chance to see them, that is
+/// until the start of the second run after they were made.
+/// ```rust
+/// use jest::{
+///     world::World,
+///     entities::builder::EntityBuilder,
+///     removal::RemovedComponents,
+///     resources::{ResMut, Resource},
+///     systems::schedule::Schedule,
+/// };
+///
+/// struct Collider;
+/// #[derive(Resource)]
+/// struct Bodies(Vec<u32>);
+///
+/// async fn drop_bodies(removed: RemovedComponents<Collider>, mut bodies: ResMut<Bodies>) {
+///     for _ in removed.iter() {
+///         bodies.get_mut().await.unwrap().0.pop();
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     world.insert_resource(Bodies(vec![0])).await;
+///     let mut builder = EntityBuilder::new();
+///     builder.add(Collider).unwrap();
+///     let entity_id = builder.build(&world).await;
+///
+///     let mut schedule = Schedule::new();
+///     schedule.add_system(drop_bodies);
+///     world.remove(entity_id).await;
+///     schedule.run(&world).await;
+///     schedule.run(&world).await;
+///
+///     assert!(world.get_resource::<Bodies>().await.unwrap().0.is_empty());
+/// }
+/// ```
+pub struct RemovedComponents<T> {
+    world: Arc<World>,
+    last_run: u64,
+    _marker: PhantomData<fn() -> T>,
+}
+impl<T: Component> RemovedComponents<T> {
+    /// Returns the IDs of the entities the component was removed from, in the order of
+    /// the removals. An entity may appear more than once if the component was added back
+    /// and removed again.
+    pub fn iter(&self) -> impl Iterator<Item = EntityId> {
+        self.world
+            .removed_since(TypeId::of::<T>(), self.last_run)
+            .into_iter()
+    }
+}
+
+/// Removed components can be taken by a system. They don't access any components while
+/// it runs.
+impl<T: Component> SystemParam for RemovedComponents<T> {
+    fn access(_access: &mut Vec<Access>) {}
+
+    fn fetch(
+        world: &Arc<World>,
+        last_run: u64,
+        _this_run: u64,
+    ) -> impl Future<Output = Self> + Send {
+        let removed = RemovedComponents {
+            world: world.clone(),
+            last_run,
+            _marker: PhantomData,
+        };
+        async { removed }
+    }
+}
@@ -0,0 +1,363 @@
+use std::{
+    any::{type_name, Any, TypeId},
+    cell::UnsafeCell,
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    future::Future,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::{self, Arc},
+};
+
+use tokio::sync::{RwLock, RwLockMappedWriteGuard, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{entities::fetch::Access, systems::SystemParam, world::World};
+
+/// Derives [`Resource`] for a type, configured with `#[resource(...)]` attributes:
+/// - `name = "..."`: the [name](Resource::NAME) of the resource, which defaults to the
+///   name of the type.
+/// - `default`: the resource is inserted with its [`Default`] value when its type is
+///   registered.
+pub use jest_macros::Resource;
+
+/// Per-type information about a resource, usually derived with
+/// [`#[derive(Resource)]`](macro@Resource). Resources are read through
+/// [`World::get_resource`], [`Res`] and [`ResMut`], which all need it so that a missing
+/// resource can be reported by name. The name and initial value only take effect once
+/// the type is registered with [`World::register_resource`].
+/// ```rust
+/// use jest::{world::World, resources::Resource};
+///
+/// #[derive(Resource, Default)]
+/// #[resource(name = "score", default)]
+/// struct Score(u32);
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     world.register_resource::<Score>().await;
+///
+///     assert_eq!(world.get_resource::<Score>().await.unwrap().0, 0);
+///     assert_eq!(world.resource_names(), ["score"]);
+/// }
+/// ```
+pub trait Resource: Any + Send + Sync + Sized {
+    /// The name this resource is listed under by [`World::resource_names`], which must be
+    /// unique among the registered resources of a world.
+    const NAME: &'static str;
+
+    /// Returns the value this resource is inserted with when its type is registered, if
+    /// any.
+    fn initial() -> Option<Self> {
+        None
+    }
+}
+
+/// Error type returned from [`World::get_resource`] and [`World::get_resource_mut`]
+#[derive(Debug)]
+pub enum ResourceError {
+    /// The world doesn't have a resource of the requested type.
+    Missing {
+        /// The [name](Resource::NAME) of the requested resource.
+        name: &'static str,
+        /// The names of the resource types registered with the world, sorted.
+        registered: Vec<&'static str>,
+    },
+    /// The world doesn't have a resource of the requested type, and the requested
+    /// resource's name is registered for a different type.
+    WrongType {
+        /// The [name](Resource::NAME) of the requested resource.
+        name: &'static str,
+        /// The name of the requested type.
+        requested: &'static str,
+        /// The name of the type registered under `name`.
+        registered: &'static str,
+    },
+}
+impl Display for ResourceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceError::Missing { name, registered } if registered.is_empty() => {
+                write!(
+                    f,
+                    "resource `{name}` isn't in the world, which registered none"
+                )
+            }
+            ResourceError::Missing { name, registered } => write!(
+                f,
+                "resource `{name}` isn't in the world, which registered `{}`",
+                registered.join("`, `")
+            ),
+            ResourceError::WrongType {
+                name,
+                requested,
+                registered,
+            } => write!(
+                f,
+                "resource `{name}` is registered as `{registered}`, not `{requested}`"
+            ),
+        }
+    }
+}
+impl Error for ResourceError {}
+
+/// The resources of a world, stored the same way as its entities: the map is guarded by
+/// an outer lock, and every resource has its own lock.
+pub(crate) struct Resources {
+    map: UnsafeCell<HashMap<TypeId, RwLock<Box<dyn Any + Send + Sync>>>>,
+    outer: RwLock<()>,
+    // the registered resource types by name, along with their type names
+    names: sync::RwLock<HashMap<&'static str, (TypeId, &'static str)>>,
+}
+impl Resources {
+    pub(crate) fn new() -> Self {
+        Self {
+            map: UnsafeCell::new(HashMap::new()),
+            outer: RwLock::new(()),
+            names: sync::RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Registers `T` under its name, then inserts its initial value unless the map already
+    /// has a resource of type `T`.
+    pub(crate) async fn register<T: Resource>(&self) {
+        {
+            let mut names = self.names.write().unwrap();
+            if let Some((id, other)) = names.get(T::NAME) {
+                assert!(
+                    *id == TypeId::of::<T>(),
+                    "resource name `{}` of `{}` is already used by `{other}`",
+                    T::NAME,
+                    type_name::<T>()
+                );
+            }
+            names.insert(T::NAME, (TypeId::of::<T>(), type_name::<T>()));
+        }
+        if let Some(initial) = T::initial() {
+            self.insert_if_absent(|| initial).await;
+        }
+    }
+
+    /// Returns the names of the registered resource types, sorted.
+    pub(crate) fn names(&self) -> Vec<&'static str> {
+        let mut names: Vec<_> = self.names.read().unwrap().keys().copied().collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Explains why the map has no resource of type `T`.
+    pub(crate) fn missing<T: Resource>(&self) -> ResourceError {
+        match self.names.read().unwrap().get(T::NAME) {
+            Some(&(id, registered)) if id != TypeId::of::<T>() => ResourceError::WrongType {
+                name: T::NAME,
+                requested: type_name::<T>(),
+                registered,
+            },
+            _ => ResourceError::Missing {
+                name: T::NAME,
+                registered: self.names(),
+            },
+        }
+    }
+
+    pub(crate) async fn insert<T: Any + Send + Sync>(&self, resource: T) -> Option<T> {
+        let _outer = self.outer.write().await;
+        let old = unsafe { &mut *self.map.get() }
+            .insert(TypeId::of::<T>(), RwLock::new(Box::new(resource)))?;
+        Some(*old.into_inner().downcast().unwrap())
+    }
+
+    /// Inserts the resource made by `f`, unless the map already has one of type `T`.
+    /// Returns whether it was inserted.
+    pub(crate) async fn insert_if_absent<T: Any + Send + Sync>(
+        &self,
+        f: impl FnOnce() -> T,
+    ) -> bool {
+        let _outer = self.outer.write().await;
+        let map = unsafe { &mut *self.map.get() };
+        if map.contains_key(&TypeId::of::<T>()) {
+            return false;
+        }
+        map.insert(TypeId::of::<T>(), RwLock::new(Box::new(f())));
+        true
+    }
+
+    pub(crate) async fn remove<T: Any + Send + Sync>(&self) -> Option<T> {
+        let _outer = self.outer.write().await;
+        let old = unsafe { &mut *self.map.get() }.remove(&TypeId::of::<T>())?;
+        Some(*old.into_inner().downcast().unwrap())
+    }
+
+    pub(crate) async fn get<T: Any + Send + Sync>(&self) -> Option<ResourceRef<'_, T>> {
+        let _outer = self.outer.read().await;
+        let inner = unsafe { &*self.map.get() }.get(&TypeId::of::<T>())?;
+        Some(ResourceRef {
+            _outer,
+            inner: RwLockReadGuard::map(inner.read().await, |resource| {
+                resource.downcast_ref().unwrap()
+            }),
+        })
+    }
+
+    pub(crate) async fn get_mut<T: Any + Send + Sync>(&self) -> Option<ResourceMut<'_, T>> {
+        let _outer = self.outer.read().await;
+        let inner = unsafe { &*self.map.get() }.get(&TypeId::of::<T>())?;
+        Some(ResourceMut {
+            _outer,
+            inner: RwLockWriteGuard::map(inner.write().await, |resource| {
+                resource.downcast_mut().unwrap()
+            }),
+        })
+    }
+}
+
+unsafe impl Send for Resources {}
+/// The map is only accessed while holding the outer lock, in the same way as the
+/// entities of a [`World`].
+unsafe impl Sync for Resources {}
+
+/// An immutable reference to a resource contained within a world.
+/// This type implements `Deref` for usage as a normal reference.
+///
+/// Beware that holding this reference will block adding and removing
+/// resources in the world, and block writing to this resource.
+/// Be sure to drop it as soon as you're done with it.
+pub struct ResourceRef<'a, T> {
+    _outer: RwLockReadGuard<'a, ()>,
+    inner: RwLockReadGuard<'a, T>,
+}
+/// Get a reference to the underlying resource.
+impl<T> Deref for ResourceRef<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+/// A mutable reference to a resource contained within a world.
+/// This type implements `Deref` and `DerefMut` for usage as a normal reference.
+///
+/// Beware that holding this reference will block adding and removing
+/// resources in the world, and block accessing this resource.
+/// Be sure to drop it as soon as you're done with it.
+pub struct ResourceMut<'a, T> {
+    _outer: RwLockReadGuard<'a, ()>,
+    inner: RwLockMappedWriteGuard<'a, T>,
+}
+/// Get a reference to the underlying resource.
+impl<T> Deref for ResourceMut<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+/// Get a mutable reference to the underlying resource.
+impl<T> DerefMut for ResourceMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+/// Reports an access to the resource `T`. Resources are keyed by `Res<T>` rather than
+/// `T`, so they never conflict with a component of the same type.
+pub(crate) fn access<T: Any>(access: &mut Vec<Access>, mutable: bool) {
+    access.push(Access {
+        id: TypeId::of::<Res<T>>(),
+        name: type_name::<T>(),
+        mutable,
+        filter: false,
+    });
+}
+
+/// A system parameter giving read access to the resource `T`.
+/// Like a [`Query`](crate::query::Query), it doesn't lock anything until
+/// [`get`](Res::get) is called.
+/// ```rust
+/// use jest::{world::World, resources::{Res, ResMut, Resource}, systems::schedule::Schedule};
+///
+/// #[derive(Resource)]
+/// struct Score(u32);
+/// #[derive(Resource)]
+/// struct Bonus(u32);
+///
+/// async fn add_bonus(mut score: ResMut<Score>, bonus: Res<Bonus>) {
+///     let bonus = bonus.get().await.unwrap();
+///     score.get_mut().await.unwrap().0 += bonus.0;
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     world.insert_resource(Score(0)).await;
+///     world.insert_resource(Bonus(5)).await;
+///
+///     let mut schedule = Schedule::new();
+///     schedule.add_system(add_bonus);
+///     schedule.run(&world).await;
+///
+///     assert_eq!(world.get_resource::<Score>().await.unwrap().0, 5);
+/// }
+/// ```
+pub struct Res<T> {
+    world: Arc<World>,
+    _marker: PhantomData<fn() -> T>,
+}
+impl<T: Resource> Res<T> {
+    /// Read-locks the resource, returning [`ResourceError`] if the world doesn't have it.
+    pub async fn get(&self) -> Result<ResourceRef<'_, T>, ResourceError> {
+        self.world.get_resource().await
+    }
+}
+impl<T: Resource> SystemParam for Res<T> {
+    fn access(access: &mut Vec<Access>) {
+        self::access::<T>(access, false);
+    }
+
+    fn fetch(
+        world: &Arc<World>,
+        _last_run: u64,
+        _this_run: u64,
+    ) -> impl Future<Output = Self> + Send {
+        let res = Res {
+            world: world.clone(),
+            _marker: PhantomData,
+        };
+        async { res }
+    }
+}
+
+/// A system parameter giving read and write access to the resource `T`.
+/// See [`Res`] for an example.
+pub struct ResMut<T> {
+    world: Arc<World>,
+    _marker: PhantomData<fn() -> T>,
+}
+impl<T: Resource> ResMut<T> {
+    /// Read-locks the resource, returning [`ResourceError`] if the world doesn't have it.
+    pub async fn get(&self) -> Result<ResourceRef<'_, T>, ResourceError> {
+        self.world.get_resource().await
+    }
+
+    /// Write-locks the resource, returning [`ResourceError`] if the world doesn't have it.
+    pub async fn get_mut(&mut self) -> Result<ResourceMut<'_, T>, ResourceError> {
+        self.world.get_resource_mut().await
+    }
+}
+impl<T: Resource> SystemParam for ResMut<T> {
+    fn access(access: &mut Vec<Access>) {
+        self::access::<T>(access, true);
+    }
+
+    fn fetch(
+        world: &Arc<World>,
+        _last_run: u64,
+        _this_run: u64,
+    ) -> impl Future<Output = Self> + Send {
+        let res = ResMut {
+            world: world.clone(),
+            _marker: PhantomData,
+        };
+        async { res }
+    }
+}
@@ -0,0 +1,134 @@
+use std::ops::Range;
+
+use crate::resources::Resource;
+
+/// A seeded random number generator, producing the same numbers on every machine for
+/// the same seed, as lockstep networking, replays and reproducible tests need.
+///
+/// Inserted as a resource, it is advanced by every [`Schedule`](crate::systems::schedule::Schedule)
+/// run: the numbers drawn during a run only depend on the seed, the number of runs
+/// before it and the draws made earlier in the same run. A run drawing more or fewer
+/// numbers than another machine's thus doesn't desynchronize the following runs.
+/// Systems taking [`ResMut<Rng>`](crate::resources::ResMut) conflict with each other,
+/// so they draw in the order they were added to the schedule.
+///
+/// Entities that need their own stream, independent of the order in which they are
+/// visited, can carry a [`fork`](Rng::fork) of it as a component.
+/// ```rust
+/// use jest::{world::World, resources::ResMut, rng::Rng, systems::schedule::Schedule};
+///
+/// async fn roll(mut rng: ResMut<Rng>) {
+///     let roll = rng.get_mut().await.unwrap().range(1..7);
+///     assert!((1..7).contains(&roll));
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     world.insert_resource(Rng::new(42)).await;
+///
+///     let mut schedule = Schedule::new();
+///     schedule.add_system(roll);
+///     schedule.run(&world).await;
+///     assert_eq!(world.get_resource::<Rng>().await.unwrap().tick(), 1);
+///
+///     // the same seed, tick and draws always give the same numbers
+///     let mut replay = Rng::new(42);
+///     replay.advance();
+///     replay.range(1..7);
+///     let mut rng = world.get_resource_mut::<Rng>().await.unwrap();
+///     assert_eq!(rng.next_u64(), replay.next_u64());
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rng {
+    seed: u64,
+    tick: u64,
+    state: u64,
+}
+impl Rng {
+    /// Creates a generator from `seed`, at tick 0.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            tick: 0,
+            state: seed,
+        }
+    }
+
+    /// Returns the seed the generator was created from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Returns the number of times the generator was [advanced](Rng::advance).
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Moves on to the next tick, starting its numbers over from the seed and the tick
+    /// alone.
+    pub fn advance(&mut self) {
+        self.tick += 1;
+        self.state = self.seed ^ mix(self.tick);
+    }
+
+    /// Returns a new generator seeded from this one's seed and `stream`, such as the
+    /// index and generation of an entity combined. It doesn't depend on the numbers
+    /// drawn so far.
+    pub fn fork(&self, stream: u64) -> Rng {
+        Rng::new(mix(self.seed) ^ mix(stream.wrapping_add(1)))
+    }
+
+    /// Returns a random `u64`.
+    pub fn next_u64(&mut self) -> u64 {
+        // SplitMix64
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        mix(self.state)
+    }
+
+    /// Returns a random `u32`.
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Returns a random `f64` between 0, included, and 1, excluded.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns a random `f32` between 0, included, and 1, excluded.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u32 << 24) as f32
+    }
+
+    /// Returns a random number within `range`, every number being as likely.
+    ///
+    /// # Panics
+    /// Panics if `range` is empty.
+    pub fn range(&mut self, range: Range<u64>) -> u64 {
+        assert!(!range.is_empty(), "can't pick a number in an empty range");
+        let span = range.end - range.start;
+        // Lemire's method: the high half of the product is uniform once the low halves
+        // that would favor some numbers are rejected
+        let threshold = span.wrapping_neg() % span;
+        loop {
+            let product = self.next_u64() as u128 * span as u128;
+            if product as u64 >= threshold {
+                return range.start + (product >> 64) as u64;
+            }
+        }
+    }
+}
+impl Resource for Rng {
+    const NAME: &'static str = "Rng";
+}
+
+/// Scrambles the bits of `value`, the output function of SplitMix64.
+fn mix(value: u64) -> u64 {
+    let mut z = value;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
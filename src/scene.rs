@@ -0,0 +1,424 @@
+use std::{
+    any::TypeId,
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::{self, Display, Formatter},
+    marker::PhantomData,
+    sync::Arc,
+};
+
+use ron::{error::SpannedError, value::RawValue};
+use serde::{
+    de::{self, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use crate::{
+    entities::{component::Component, Entity, EntityId},
+    hierarchy,
+    serialization::Registry,
+    world::World,
+};
+
+/// A set of entities described in [RON](https://github.com/ron-rs/ron) text, which can be
+/// spawned into a world as many times as needed.
+///
+/// A scene lists its `entities`, and may define `prefabs`: named entities that other
+/// entities are based on. Each entity has `components`, a map from type names to values,
+/// may have `children`, which are spawned as its [children](crate::hierarchy), and may be
+/// based on a `prefab`. An entity based on a prefab starts with the prefab's components
+/// and children, then adds its own, replacing the prefab's components of the same types.
+/// Prefabs can themselves be based on, or have children based on, other prefabs.
+///
+/// Components are read with the types registered with
+/// [`World::register_serializable`](World::register_serializable), either by their full
+/// [type name](std::any::type_name) or by the name without its module path.
+/// ```rust
+/// use jest::{world::World, hierarchy::Children, scene::Scene};
+/// use serde::{Deserialize, Deserializer};
+///
+/// struct Health(u32);
+/// impl<'de> Deserialize<'de> for Health {
+///     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+///         u32::deserialize(deserializer).map(Health)
+///     }
+/// }
+/// # impl serde::Serialize for Health {
+/// #     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+/// #         self.0.serialize(serializer)
+/// #     }
+/// # }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let scene = Scene::from_ron(r#"
+///         Scene(
+///             prefabs: {
+///                 "crate": (components: { "Health": 10 }),
+///                 "tower": (
+///                     components: { "Health": 50 },
+///                     children: [(prefab: "crate"), (prefab: "crate")],
+///                 ),
+///             },
+///             entities: [
+///                 (prefab: "tower"),
+///                 // a sturdier crate
+///                 (prefab: "crate", components: { "Health": 20 }),
+///             ],
+///         )
+///     "#).unwrap();
+///
+///     let world = World::new();
+///     world.register_serializable::<Health>();
+///     let spawned = scene.spawn(&world).await.unwrap();
+///
+///     let tower = world.get(spawned[0]).await.unwrap();
+///     assert_eq!(tower.get::<Health>().unwrap().0, 50);
+///     let first_crate = tower.get::<Children>().unwrap()[0];
+///     drop(tower);
+///     assert_eq!(world.get(first_crate).await.unwrap().get::<Health>().unwrap().0, 10);
+///     assert_eq!(world.get(spawned[1]).await.unwrap().get::<Health>().unwrap().0, 20);
+/// }
+/// ```
+pub struct Scene {
+    source: String,
+    prefabs: HashMap<String, Description>,
+    entities: Vec<Description>,
+}
+
+/// An entity described by a scene.
+struct Description {
+    prefab: Option<String>,
+    // the type name of each component, along with where its value starts and ends
+    components: Vec<(String, usize, usize)>,
+    children: Vec<Description>,
+}
+
+/// Error type returned from [`Scene::from_ron`] and [`Scene::spawn`]
+#[derive(Debug)]
+pub enum SceneError {
+    /// The text isn't valid RON, doesn't describe a scene, or contains a component that
+    /// can't be deserialized. The position is 1-based.
+    Parse {
+        /// The line of the error.
+        line: usize,
+        /// The column of the error, in characters.
+        column: usize,
+        /// What went wrong.
+        message: String,
+    },
+    /// An entity is based on a prefab that the scene doesn't define.
+    UnknownPrefab(String),
+    /// A prefab is based on itself, or has itself as a descendant.
+    PrefabCycle(String),
+    /// A component's type isn't registered with the world.
+    UnknownComponent(String),
+    /// An entity lists two components that are names of the same type, such as
+    /// `Health` and `game::Health`.
+    DuplicateComponent {
+        /// The first name listed.
+        first: String,
+        /// The name listed later.
+        second: String,
+    },
+    /// A component is a [`Parent`](crate::hierarchy::Parent) or
+    /// [`Children`](crate::hierarchy::Children), which are set from the `children` of
+    /// the entities instead.
+    HierarchyComponent(String),
+}
+impl Display for SceneError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Parse {
+                line,
+                column,
+                message,
+            } => write!(f, "{message} at {line}:{column}"),
+            SceneError::UnknownPrefab(name) => write!(f, "unknown prefab `{name}`"),
+            SceneError::PrefabCycle(name) => write!(f, "prefab `{name}` contains itself"),
+            SceneError::UnknownComponent(name) => {
+                write!(f, "component `{name}` isn't registered as serializable")
+            }
+            SceneError::DuplicateComponent { first, second } => {
+                write!(f, "components `{first}` and `{second}` are the same type")
+            }
+            SceneError::HierarchyComponent(name) => {
+                write!(
+                    f,
+                    "component `{name}` is part of the hierarchy, list children instead"
+                )
+            }
+        }
+    }
+}
+impl Error for SceneError {}
+
+impl Scene {
+    /// Parses a scene from RON text. Only the structure of the scene is checked here, as
+    /// the components are deserialized by [`spawn`](Scene::spawn).
+    pub fn from_ron(source: &str) -> Result<Self, SceneError> {
+        let file: SceneFile<'_> =
+            ron::from_str(source).map_err(|err| parse_error(source, 0, err))?;
+        let scene = Self {
+            source: source.to_owned(),
+            prefabs: file
+                .prefabs
+                .0
+                .into_iter()
+                .map(|(name, entity)| (name, entity.describe(source)))
+                .collect(),
+            entities: file
+                .entities
+                .into_iter()
+                .map(|entity| entity.describe(source))
+                .collect(),
+        };
+        let mut visited = HashMap::new();
+        for name in scene.prefabs.keys() {
+            scene.check_prefab(name, &mut visited)?;
+        }
+        for description in &scene.entities {
+            scene.check_references(description, &mut visited)?;
+        }
+        Ok(scene)
+    }
+
+    /// Checks that the prefab `name` doesn't contain itself. `visited` tells whether each
+    /// prefab is fully checked, or is being checked further up.
+    fn check_prefab<'a>(
+        &'a self,
+        name: &'a str,
+        visited: &mut HashMap<&'a str, bool>,
+    ) -> Result<(), SceneError> {
+        match visited.get(name) {
+            Some(true) => return Ok(()),
+            Some(false) => return Err(SceneError::PrefabCycle(name.to_owned())),
+            None => {}
+        }
+        let description = self
+            .prefabs
+            .get(name)
+            .ok_or_else(|| SceneError::UnknownPrefab(name.to_owned()))?;
+        visited.insert(name, false);
+        self.check_references(description, visited)?;
+        visited.insert(name, true);
+        Ok(())
+    }
+
+    /// Checks the prefabs referred to by `description` and its children.
+    fn check_references<'a>(
+        &'a self,
+        description: &'a Description,
+        visited: &mut HashMap<&'a str, bool>,
+    ) -> Result<(), SceneError> {
+        if let Some(prefab) = &description.prefab {
+            self.check_prefab(prefab, visited)?;
+        }
+        for child in &description.children {
+            self.check_references(child, visited)?;
+        }
+        Ok(())
+    }
+
+    /// Spawns every entity of the scene into `world`, returning the IDs of the top-level
+    /// entities in the order they are listed. If a component can't be deserialized,
+    /// nothing is spawned.
+    pub async fn spawn(&self, world: &Arc<World>) -> Result<Vec<EntityId>, SceneError> {
+        let mut entities = Vec::new();
+        let mut links = Vec::new();
+        let roots = {
+            let registry = world.serializables.read().unwrap();
+            let mut instances = Instances {
+                world,
+                registry: &registry,
+                entities: &mut entities,
+                links: &mut links,
+            };
+            self.entities
+                .iter()
+                .map(|description| self.instantiate(description, &mut instances))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        let ids = world.insert_many(entities).await;
+        for (parent, child) in links {
+            // this can only fail if the entities have been despawned since
+            if let Some(mut parent) = world.get_mut(ids[parent]).await {
+                parent.add_child(ids[child]).await.ok();
+            }
+        }
+        Ok(roots.into_iter().map(|index| ids[index]).collect())
+    }
+
+    /// Creates the entity described by `description` and its descendants, returning its
+    /// index in `instances`.
+    fn instantiate(
+        &self,
+        description: &Description,
+        instances: &mut Instances<'_>,
+    ) -> Result<usize, SceneError> {
+        let mut components = HashMap::new();
+        let mut children = Vec::new();
+        self.collect(
+            description,
+            instances.registry,
+            &mut components,
+            &mut children,
+        )?;
+        let index = instances.entities.len();
+        instances
+            .entities
+            .push(Entity::new(components, instances.world.clone()));
+        for child in children {
+            let child = self.instantiate(child, instances)?;
+            instances.links.push((index, child));
+        }
+        Ok(index)
+    }
+
+    /// Gathers the components and children of `description`, including those of the
+    /// prefab it is based on. Its own components replace the prefab's.
+    fn collect<'a>(
+        &'a self,
+        description: &'a Description,
+        registry: &Registry,
+        components: &mut HashMap<TypeId, Box<dyn Component>>,
+        children: &mut Vec<&'a Description>,
+    ) -> Result<(), SceneError> {
+        if let Some(prefab) = &description.prefab {
+            self.collect(&self.prefabs[prefab], registry, components, children)?;
+        }
+        // the name each component type is listed under
+        let mut names = HashMap::new();
+        for (name, start, end) in &description.components {
+            let id = registry
+                .find(name)
+                .ok_or_else(|| SceneError::UnknownComponent(name.clone()))?;
+            if hierarchy::is_link(id) {
+                return Err(SceneError::HierarchyComponent(name.clone()));
+            }
+            if let Some(first) = names.insert(id, name) {
+                return Err(SceneError::DuplicateComponent {
+                    first: first.clone(),
+                    second: name.clone(),
+                });
+            }
+            let component = registry
+                .read_ron(id, &self.source[*start..*end])
+                .map_err(|err| parse_error(&self.source, *start, err))?;
+            components.insert(id, component);
+        }
+        children.extend(&description.children);
+        Ok(())
+    }
+}
+
+/// The entities being spawned from a scene, in the order they are created.
+struct Instances<'a> {
+    world: &'a Arc<World>,
+    registry: &'a Registry,
+    entities: &'a mut Vec<Entity>,
+    // the indices of every parent and child
+    links: &'a mut Vec<(usize, usize)>,
+}
+
+/// Converts an error from parsing the text of `source` that starts at `offset` to a
+/// [`SceneError::Parse`], locating it within `source`.
+fn parse_error(source: &str, offset: usize, err: SpannedError) -> SceneError {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let (line, column) = (
+        before.matches('\n').count() + err.position.line,
+        match err.position.line {
+            1 => before[line_start..].chars().count() + err.position.col,
+            _ => err.position.col,
+        },
+    );
+    SceneError::Parse {
+        line,
+        column,
+        message: err.code.to_string(),
+    }
+}
+
+/// A scene as written in RON. The component values are only borrowed from the text, to
+/// be deserialized when the scene is spawned.
+#[derive(Deserialize)]
+#[serde(rename = "Scene", deny_unknown_fields)]
+struct SceneFile<'a> {
+    #[serde(default, borrow)]
+    prefabs: Entries<EntityFile<'a>>,
+    #[serde(default, borrow)]
+    entities: Vec<EntityFile<'a>>,
+}
+
+/// An entity as written in RON, see [`SceneFile`].
+#[derive(Deserialize)]
+#[serde(rename = "Entity", deny_unknown_fields)]
+struct EntityFile<'a> {
+    #[serde(default, deserialize_with = "prefab_name")]
+    prefab: Option<String>,
+    #[serde(default, borrow)]
+    components: Entries<&'a RawValue>,
+    #[serde(default, borrow)]
+    children: Vec<EntityFile<'a>>,
+}
+impl EntityFile<'_> {
+    /// Converts the entity to a description of the scene with the text `source`, which
+    /// it was parsed from.
+    fn describe(self, source: &str) -> Description {
+        Description {
+            prefab: self.prefab,
+            components: self
+                .components
+                .0
+                .into_iter()
+                .map(|(name, value)| {
+                    let start = value.get_ron().as_ptr() as usize - source.as_ptr() as usize;
+                    (name, start, start + value.get_ron().len())
+                })
+                .collect(),
+            children: self
+                .children
+                .into_iter()
+                .map(|child| child.describe(source))
+                .collect(),
+        }
+    }
+}
+
+/// Reads the name of a prefab, which is written without `Some`.
+fn prefab_name<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    String::deserialize(deserializer).map(Some)
+}
+
+/// The entries of a map with string keys, in order. Keys can't be repeated.
+struct Entries<V>(Vec<(String, V)>);
+impl<V> Default for Entries<V> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for Entries<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntriesVisitor<V>(PhantomData<V>);
+        impl<'de, V: Deserialize<'de>> Visitor<'de> for EntriesVisitor<V> {
+            type Value = Entries<V>;
+            fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                f.write_str("a map with string keys")
+            }
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Entries<V>, A::Error> {
+                let mut keys = HashSet::new();
+                let mut entries = Vec::new();
+                while let Some(key) = map.next_key::<String>()? {
+                    if !keys.insert(key.clone()) {
+                        return Err(de::Error::custom(format_args!("duplicate key `{key}`")));
+                    }
+                    entries.push((key, map.next_value()?));
+                }
+                Ok(Entries(entries))
+            }
+        }
+        deserializer.deserialize_map(EntriesVisitor(PhantomData))
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::HashMap,
+    fmt::{self, Formatter},
+};
+
+use serde::{
+    de::{self, DeserializeOwned, MapAccess, Visitor},
+    ser, Deserialize, Deserializer, Serialize, Serializer,
+};
+use serde_value::{DeserializerError, SerializerError, Value};
+
+use crate::entities::{
+    component::Component,
+    map::{EntityMap, InsertAtError},
+    Entity, EntityId,
+};
+
+/// Converts components of one type to and from [`Value`]s, and reads them from RON.
+struct Serializable {
+    name: &'static str,
+    serialize: fn(&dyn Component) -> Result<Value, SerializerError>,
+    deserialize: fn(Value) -> Result<Box<dyn Component>, DeserializerError>,
+    from_ron: fn(&str) -> Result<Box<dyn Component>, ron::error::SpannedError>,
+}
+
+/// The component types of a world that can be serialized, by type ID and by name.
+#[derive(Default)]
+pub(crate) struct Registry {
+    by_type: HashMap<TypeId, Serializable>,
+    by_name: HashMap<&'static str, TypeId>,
+}
+impl Registry {
+    pub(crate) fn register<T: Component + Serialize + DeserializeOwned>(&mut self) {
+        let name = type_name::<T>();
+        self.by_type.insert(
+            TypeId::of::<T>(),
+            Serializable {
+                name,
+                serialize: |component| {
+                    let component: &dyn Any = component;
+                    serde_value::to_value(component.downcast_ref::<T>().unwrap())
+                },
+                deserialize: |value| Ok(Box::new(value.deserialize_into::<T>()?)),
+                from_ron: |ron| Ok(Box::new(ron::from_str::<T>(ron)?)),
+            },
+        );
+        self.by_name.insert(name, TypeId::of::<T>());
+    }
+
+    /// Converts the serializable components of `entity` to values, sorted by name.
+    pub(crate) fn serialize(&self, entity: &Entity) -> Result<Components, SerializerError> {
+        let mut components: Vec<_> = entity
+            .components()
+            .into_iter()
+            .filter_map(|(id, component)| {
+                let serializable = self.by_type.get(&id)?;
+                Some((serializable.serialize)(component).map(|value| (serializable.name, value)))
+            })
+            .collect::<Result<_, _>>()?;
+        components.sort_unstable_by_key(|(name, _)| *name);
+        Ok(Components(
+            components
+                .into_iter()
+                .map(|(name, value)| (name.to_owned(), value))
+                .collect(),
+        ))
+    }
+
+    /// Returns the type registered under `name`. A name that isn't registered matches
+    /// the type whose name ends with `::name`, if there is exactly one, so that types
+    /// can be referred to without their module path.
+    pub(crate) fn find(&self, name: &str) -> Option<TypeId> {
+        if let Some(id) = self.by_name.get(name) {
+            return Some(*id);
+        }
+        let suffix = format!("::{name}");
+        let mut found = self
+            .by_name
+            .iter()
+            .filter(|(registered, _)| registered.ends_with(&suffix));
+        match (found.next(), found.next()) {
+            (Some((_, id)), None) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// Converts values back to components, failing on any type that isn't registered.
+    pub(crate) fn deserialize(
+        &self,
+        components: Components,
+    ) -> Result<HashMap<TypeId, Box<dyn Component>>, DeserializerError> {
+        components
+            .0
+            .into_iter()
+            .map(|(name, value)| {
+                let id = self.find(&name).ok_or_else(|| {
+                    <DeserializerError as de::Error>::custom(format!("unknown component `{name}`"))
+                })?;
+                Ok((id, (self.by_type[&id].deserialize)(value)?))
+            })
+            .collect()
+    }
+
+    /// Reads a component of the type with ID `id`, which must be registered, from RON
+    /// text holding nothing but the value.
+    pub(crate) fn read_ron(
+        &self,
+        id: TypeId,
+        ron: &str,
+    ) -> Result<Box<dyn Component>, ron::error::SpannedError> {
+        (self.by_type[&id].from_ron)(ron)
+    }
+}
+
+/// The serialized components of an entity, as a map from type names to values.
+pub(crate) struct Components(Vec<(String, Value)>);
+impl Serialize for Components {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(name, value)| (name, value)))
+    }
+}
+impl<'de> Deserialize<'de> for Components {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ComponentsVisitor;
+        impl<'de> Visitor<'de> for ComponentsVisitor {
+            type Value = Components;
+            fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                f.write_str("a map from component names to components")
+            }
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Components, A::Error> {
+                let mut components = Vec::with_capacity(map.size_hint().unwrap_or(0));
+                while let Some(entry) = map.next_entry()? {
+                    components.push(entry);
+                }
+                Ok(Components(components))
+            }
+        }
+        deserializer.deserialize_map(ComponentsVisitor)
+    }
+}
+
+/// An entity as it is saved along with its world: its ID and its components.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Entity", deny_unknown_fields)]
+pub(crate) struct SavedEntity {
+    pub(crate) id: EntityId,
+    pub(crate) components: Components,
+}
+
+/// Builds an entity map holding every value at its saved ID. The slots no value is
+/// saved at are left free.
+pub(crate) fn restore<V>(values: Vec<(EntityId, V)>) -> Result<EntityMap<V>, String> {
+    let mut map = EntityMap::default();
+    for (id, value) in values {
+        map.insert_at(id, || value).map_err(|err| match err {
+            InsertAtError::Invalid => format!("invalid entity index {}", id.index()),
+            InsertAtError::Occupied | InsertAtError::Stale => {
+                format!("duplicate entity index {}", id.index())
+            }
+        })?;
+    }
+    Ok(map)
+}
+
+impl Serialize for Entity {
+    /// Serializes the components of this entity whose types are registered with
+    /// [`World::register_serializable`](crate::world::World::register_serializable),
+    /// as a map from type names to components. Other components are skipped.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let components = self
+            .world
+            .serializables
+            .read()
+            .unwrap()
+            .serialize(self)
+            .map_err(ser::Error::custom)?;
+        components.serialize(serializer)
+    }
+}
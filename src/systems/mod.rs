@@ -0,0 +1,295 @@
+use std::{
+    any::type_name,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::{
+    entities::{
+        fetch::{self, Access},
+        EntityId,
+    },
+    world::World,
+    Error,
+};
+
+/// Running systems once per tick.
+pub mod schedule;
+
+/// A piece of game logic that runs against a [`World`], usually once per tick
+/// as part of a [`Schedule`](schedule::Schedule).
+///
+/// Systems are normally written as plain async functions whose arguments are all
+/// [`SystemParam`]s, such as [`Query`](crate::query::Query), and turned into a
+/// `System` with [`IntoSystem`]:
+/// ```rust
+/// use jest::{
+///     world::World,
+///     entities::builder::EntityBuilder,
+///     query::Query,
+///     systems::schedule::Schedule,
+/// };
+///
+/// struct Position(f32);
+/// struct Velocity(f32);
+///
+/// async fn movement(mut query: Query<(&mut Position, &Velocity)>) {
+///     let mut iter = query.iter().await;
+///     while let Some((_id, (position, velocity))) = iter.next().await {
+///         position.0 += velocity.0;
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     let mut builder = EntityBuilder::new();
+///     builder.add_bundle((Position(0.0), Velocity(1.5))).unwrap();
+///     let entity_id = builder.build(&world).await;
+///
+///     let mut schedule = Schedule::new();
+///     schedule.add_system(movement);
+///     schedule.run(&world).await;
+///     schedule.run(&world).await;
+///
+///     let entity = world.get(entity_id).await.unwrap();
+///     assert_eq!(entity.get::<Position>().unwrap().0, 3.0);
+/// }
+/// ```
+pub trait System: Send + Sync {
+    /// Returns the name of the system, for diagnostics.
+    fn name(&self) -> &str;
+
+    /// Returns the component types the system reads and writes. A [`Schedule`](schedule::Schedule)
+    /// runs systems whose accesses don't conflict at the same time.
+    fn access(&self) -> &[Access];
+
+    /// Runs the system once against `world`. An error is handed to the
+    /// [error handler](schedule::Schedule::set_error_handler) of the schedule.
+    fn run<'a>(
+        &'a self,
+        world: &'a Arc<World>,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>>;
+}
+
+/// What a function system can return: `()`, or a `Result` whose error converts into
+/// an [`Error`], for systems that can fail.
+pub trait SystemOutput {
+    /// Converts the output into the result of a [`System::run`].
+    fn into_result(self) -> Result<(), Error>;
+}
+impl SystemOutput for () {
+    fn into_result(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+impl<E: Into<Error>> SystemOutput for Result<(), E> {
+    fn into_result(self) -> Result<(), Error> {
+        self.map_err(Into::into)
+    }
+}
+
+/// A value that can be passed to a function system, created fresh from the world
+/// every time the system runs.
+pub trait SystemParam: Send + Sized + 'static {
+    /// Adds the component types the parameter reads and writes to `access`.
+    fn access(access: &mut Vec<Access>);
+
+    /// Creates the parameter for a run of a system against `world`. `last_run` is the
+    /// world's [change tick](World::change_tick) when the system last ran, or 0 if it
+    /// never did, and `this_run` the tick of the current run, which the changes it
+    /// makes are marked with.
+    fn fetch(world: &Arc<World>, last_run: u64, this_run: u64)
+        -> impl Future<Output = Self> + Send;
+}
+
+/// Conversion into a [`System`]. This is implemented for every [`System`], and for
+/// async functions taking up to 8 [`SystemParam`]s and returning a [`SystemOutput`].
+/// `Marker` only serves to tell the implementations apart.
+///
+/// A function returning a `Result` can fail without panicking, and without stopping
+/// the other systems of the schedule:
+/// ```rust
+/// use jest::{world::World, resources::{Resource, Res}, systems::schedule::Schedule};
+///
+/// #[derive(Resource)]
+/// struct Config(u32);
+///
+/// // fails while the resource is missing
+/// async fn read_config(config: Res<Config>) -> Result<(), jest::Error> {
+///     let config = config.get().await?;
+///     assert_eq!(config.0, 60);
+///     Ok(())
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     let mut schedule = Schedule::new();
+///     schedule.add_system(read_config);
+///     // the error is logged, and the schedule carries on
+///     schedule.run(&world).await;
+///     world.insert_resource(Config(60)).await;
+///     schedule.run(&world).await;
+/// }
+/// ```
+pub trait IntoSystem<Marker> {
+    /// The system produced.
+    type System: System + 'static;
+
+    /// Converts `self` into a system.
+    ///
+    /// # Panics
+    /// Converting a function panics if its parameters access the same component or
+    /// resource mutably more than once, or both mutably and immutably, e.g. through
+    /// a `Query<&mut T>` and a `Query<&T>`. Iterating both at once would otherwise
+    /// deadlock on the entity locks.
+    ///
+    /// Queries over different components can still lock the same entity, so while the
+    /// system runs, a query reaching an entity that another of its queries has locked
+    /// panics with the name of the system instead of deadlocking. Avoid iterating one
+    /// query inside the loop of another unless they match different entities.
+    fn into_system(self) -> Self::System;
+}
+
+tokio::task_local! {
+    // the function system the current task is running
+    static RUNNING: Running;
+}
+
+/// A run of a function system, along with the entities its queries have locked.
+struct Running {
+    name: &'static str,
+    locked: Mutex<Vec<EntityId>>,
+}
+
+/// An entity a query of the running system is locking, released once dropped.
+pub(crate) struct Claim(Option<EntityId>);
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if let Some(id) = self.0 {
+            let _ = RUNNING.try_with(|running| {
+                if let Ok(mut locked) = running.locked.lock() {
+                    locked.retain(|locked| *locked != id);
+                }
+            });
+        }
+    }
+}
+
+/// Claims the entity `id` for a query about to lock it. Outside of a function system
+/// nothing is tracked.
+///
+/// # Panics
+/// Panics if another query of the running system already claimed the entity, as
+/// locking it again would deadlock.
+pub(crate) fn claim(id: EntityId) -> Claim {
+    let claimed = RUNNING.try_with(|running| {
+        let mut locked = running.locked.lock().unwrap();
+        if locked.contains(&id) {
+            return Err(running.name);
+        }
+        locked.push(id);
+        Ok(())
+    });
+    match claimed {
+        Ok(Ok(())) => Claim(Some(id)),
+        Ok(Err(name)) => panic!(
+            "entity {id} is locked by two queries of system `{name}` at once, which would deadlock"
+        ),
+        Err(_) => Claim(None),
+    }
+}
+
+impl<S: System + 'static> IntoSystem<()> for S {
+    type System = S;
+
+    fn into_system(self) -> S {
+        self
+    }
+}
+
+/// A [`System`] made from an async function, see [`IntoSystem`].
+pub struct FunctionSystem<F, Marker> {
+    f: F,
+    access: Vec<Access>,
+    last_run: AtomicU64,
+    _marker: PhantomData<fn() -> Marker>,
+}
+
+macro_rules! impl_function_system {
+    ($($param:ident),*) => {
+        impl<F, Fut, $($param),*> System for FunctionSystem<F, (Fut, $($param,)*)>
+        where
+            F: Fn($($param),*) -> Fut + Send + Sync + 'static,
+            Fut: Future + Send,
+            Fut::Output: SystemOutput,
+            $($param: SystemParam,)*
+        {
+            fn name(&self) -> &str {
+                type_name::<F>()
+            }
+
+            fn access(&self) -> &[Access] {
+                &self.access
+            }
+
+            #[allow(non_snake_case, unused_variables)]
+            fn run<'a>(
+                &'a self,
+                world: &'a Arc<World>,
+            ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>> {
+                // the changes made by this run are newer than `last_run` for every
+                // other system, but not for this one the next time it runs
+                let this_run = world.increment_change_tick();
+                let last_run = self.last_run.swap(this_run, Ordering::AcqRel);
+                let running = Running {
+                    name: type_name::<F>(),
+                    locked: Mutex::new(Vec::new()),
+                };
+                Box::pin(RUNNING.scope(running, async move {
+                    $(let $param = $param::fetch(world, last_run, this_run).await;)*
+                    (self.f)($($param),*).await.into_result()
+                }))
+            }
+        }
+
+        impl<F, Fut, $($param),*> IntoSystem<(Fut, $($param,)*)> for F
+        where
+            F: Fn($($param),*) -> Fut + Send + Sync + 'static,
+            Fut: Future + Send + 'static,
+            Fut::Output: SystemOutput,
+            $($param: SystemParam,)*
+        {
+            type System = FunctionSystem<F, (Fut, $($param,)*)>;
+
+            #[allow(unused_mut)]
+            fn into_system(self) -> Self::System {
+                let mut access = Vec::new();
+                $($param::access(&mut access);)*
+                fetch::assert_disjoint(&access, &format!("system `{}`", type_name::<F>()));
+                FunctionSystem {
+                    f: self,
+                    access,
+                    last_run: AtomicU64::new(0),
+                    _marker: PhantomData,
+                }
+            }
+        }
+    };
+}
+
+impl_function_system!();
+impl_function_system!(P0);
+impl_function_system!(P0, P1);
+impl_function_system!(P0, P1, P2);
+impl_function_system!(P0, P1, P2, P3);
+impl_function_system!(P0, P1, P2, P3, P4);
+impl_function_system!(P0, P1, P2, P3, P4, P5);
+impl_function_system!(P0, P1, P2, P3, P4, P5, P6);
+impl_function_system!(P0, P1, P2, P3, P4, P5, P6, P7);
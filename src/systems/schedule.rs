@@ -0,0 +1,460 @@
+use std::{
+    collections::HashMap,
+    panic,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::watch;
+
+use crate::{entities::fetch, resources::Resource, rng::Rng, world::World, Error};
+
+use super::{IntoSystem, System};
+
+/// An ordered list of [`System`]s that are run together, once per tick.
+///
+/// Systems whose [accesses](System::access) don't conflict run concurrently, as
+/// separate tokio tasks. A system that conflicts with one added before it, i.e. one
+/// of them writes a component the other reads, writes or filters on, waits for it to
+/// finish first, so conflicting systems always run in the order they were added.
+///
+/// Systems that fail return an [`Error`], which is handed to the schedule's
+/// [error handler](Schedule::set_error_handler) once the run is over. By default it is
+/// logged, and the system runs again on the next run.
+#[derive(Default)]
+pub struct Schedule {
+    systems: Vec<Arc<dyn System>>,
+    // the number of runs each system sits out
+    skipped: Vec<u32>,
+    error_handler: Option<ErrorHandler>,
+    stopped: bool,
+}
+
+/// A function deciding what a [`Schedule`] does about a system that failed, given the
+/// name of the system and its error.
+pub type ErrorHandler = fn(system: &str, error: &Error) -> ErrorPolicy;
+
+/// What a [`Schedule`] does about a system that failed, as returned by its
+/// [error handler](Schedule::set_error_handler).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Runs the system again on the next run.
+    Continue,
+    /// Skips the system for this many runs.
+    Skip(u32),
+    /// Stops the schedule: the current run is finished, and the following ones do
+    /// nothing. See [`Schedule::stopped`].
+    Stop,
+}
+impl Schedule {
+    /// Creates a new, empty schedule.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a system to the end of the schedule.
+    pub fn add_system<M>(&mut self, system: impl IntoSystem<M>) -> &mut Self {
+        self.systems.push(Arc::new(system.into_system()));
+        self.skipped.push(0);
+        self
+    }
+
+    /// Sets the function deciding what happens when a system fails, replacing the
+    /// default one, which logs the error with `tracing` if the feature is enabled, or
+    /// to stderr otherwise, and returns [`ErrorPolicy::Continue`].
+    /// ```rust
+    /// use jest::{world::World, systems::schedule::{ErrorPolicy, Schedule}};
+    ///
+    /// async fn connect() -> Result<(), jest::Error> {
+    ///     Err("the server is down".into())
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///     let mut schedule = Schedule::new();
+    ///     schedule
+    ///         .add_system(connect)
+    ///         .set_error_handler(|_system, _error| ErrorPolicy::Stop);
+    ///     schedule.run(&world).await;
+    ///     assert!(schedule.stopped());
+    /// }
+    /// ```
+    pub fn set_error_handler(&mut self, handler: ErrorHandler) -> &mut Self {
+        self.error_handler = Some(handler);
+        self
+    }
+
+    /// Returns whether the [error handler](Schedule::set_error_handler) stopped the
+    /// schedule, in which case running it does nothing.
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Runs every system once against `world`, then applies the [`Commands`](crate::commands::Commands)
+    /// they recorded. A [`Rng`] resource is [advanced](Rng::advance) before the systems
+    /// run. If the world has [`SystemTimings`], the time each system took is recorded in
+    /// it.
+    ///
+    /// Systems that are [skipped](ErrorPolicy::Skip) don't run, and nothing runs once
+    /// the schedule is [stopped](Schedule::stopped).
+    ///
+    /// # Panics
+    /// Must be called from within a tokio runtime. If a system panics, the panic is
+    /// resumed here after the other systems have finished.
+    pub async fn run(&mut self, world: &Arc<World>) {
+        if self.stopped {
+            return;
+        }
+        start_run(world).await;
+        let mut finished: Vec<watch::Receiver<bool>> = Vec::with_capacity(self.systems.len());
+        let mut tasks = Vec::with_capacity(self.systems.len());
+        for (i, system) in self.systems.iter().enumerate() {
+            let dependencies: Vec<_> = self.systems[..i]
+                .iter()
+                .zip(&finished)
+                .filter(|(other, _)| fetch::conflicts(other.access(), system.access()))
+                .map(|(_, finished)| finished.clone())
+                .collect();
+            let (done, receiver) = watch::channel(false);
+            finished.push(receiver);
+            if self.skipped[i] > 0 {
+                self.skipped[i] -= 1;
+                let _ = done.send(true);
+                tasks.push(None);
+                continue;
+            }
+
+            let system = system.clone();
+            let world = world.clone();
+            tasks.push(Some(tokio::spawn(async move {
+                for mut dependency in dependencies {
+                    // an error means the dependency panicked, which is resumed below
+                    let _ = dependency.wait_for(|finished| *finished).await;
+                }
+                let start = Instant::now();
+                let result = system.run(&world).await;
+                let elapsed = start.elapsed();
+                let _ = done.send(true);
+                (elapsed, result)
+            })));
+        }
+
+        let mut panicked = None;
+        let mut elapsed = Vec::with_capacity(tasks.len());
+        let handler = self.error_handler.unwrap_or(log_error);
+        for ((system, task), skipped) in self.systems.iter().zip(tasks).zip(&mut self.skipped) {
+            let Some(task) = task else {
+                continue;
+            };
+            match task.await {
+                Ok((time, result)) => {
+                    elapsed.push((system.name(), time));
+                    if let Err(error) = result {
+                        handle_error(handler, system.name(), &error, skipped, &mut self.stopped);
+                    }
+                }
+                Err(err) => {
+                    if err.is_panic() && panicked.is_none() {
+                        panicked = Some(err.into_panic());
+                    }
+                }
+            }
+        }
+        if let Some(payload) = panicked {
+            panic::resume_unwind(payload);
+        }
+        finish_run(world, elapsed).await;
+    }
+
+    /// Starts a run against `world` that is made one system at a time, in the order
+    /// they were added, so that the world can be inspected between systems, for example
+    /// to find the one corrupting a component. Each call to [`Stepper::next`] runs one
+    /// system. The run is otherwise the same as [`run`](Schedule::run), except that no
+    /// two systems run at the same time, and that failures are handled right after the
+    /// failing system.
+    /// ```rust
+    /// use jest::{
+    ///     world::World,
+    ///     entities::builder::EntityBuilder,
+    ///     query::Query,
+    ///     systems::schedule::Schedule,
+    /// };
+    ///
+    /// struct Health(i32);
+    ///
+    /// async fn poison(mut query: Query<&mut Health>) {
+    ///     let mut iter = query.iter().await;
+    ///     while let Some((_id, health)) = iter.next().await {
+    ///         health.0 -= 150;
+    ///     }
+    /// }
+    ///
+    /// async fn regenerate(mut query: Query<&mut Health>) {
+    ///     let mut iter = query.iter().await;
+    ///     while let Some((_id, health)) = iter.next().await {
+    ///         health.0 += 10;
+    ///     }
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///     let mut builder = EntityBuilder::new();
+    ///     builder.add(Health(100)).unwrap();
+    ///     let player = builder.build(&world).await;
+    ///
+    ///     let mut schedule = Schedule::new();
+    ///     schedule.add_system(poison).add_system(regenerate);
+    ///     let mut stepper = schedule.step(&world).await;
+    ///     let mut culprit = None;
+    ///     while let Some(name) = stepper.next().await {
+    ///         let health = world.get(player).await.unwrap().get::<Health>().unwrap().0;
+    ///         if health < 0 && culprit.is_none() {
+    ///             culprit = Some(name.to_owned());
+    ///         }
+    ///     }
+    ///     assert!(culprit.unwrap().ends_with("poison"));
+    /// }
+    /// ```
+    pub async fn step<'a>(&'a mut self, world: &Arc<World>) -> Stepper<'a> {
+        let finished = self.stopped;
+        if !finished {
+            start_run(world).await;
+        }
+        Stepper {
+            systems: if finished { &[] } else { &self.systems },
+            skipped: &mut self.skipped,
+            handler: self.error_handler.unwrap_or(log_error),
+            stopped: &mut self.stopped,
+            world: world.clone(),
+            elapsed: Vec::with_capacity(self.systems.len()),
+            finished,
+        }
+    }
+}
+
+/// Prepares `world` for a run of systems.
+async fn start_run(world: &Arc<World>) {
+    // removals made before the previous run started have been seen by every system,
+    // and the changes the systems make are all newer than the tick the run starts at
+    let previous = world
+        .last_change_tick
+        .swap(world.increment_change_tick(), Ordering::AcqRel);
+    world.clear_removed(previous);
+    world.update_events().await;
+    if let Ok(mut rng) = world.get_resource_mut::<Rng>().await {
+        rng.advance();
+    }
+}
+
+/// Ends a run of systems against `world`, given how long each of them took.
+async fn finish_run(world: &Arc<World>, elapsed: Vec<(&str, Duration)>) {
+    if let Ok(mut timings) = world.get_resource_mut::<SystemTimings>().await {
+        for (name, time) in elapsed {
+            timings.record(name, time);
+        }
+    }
+    world.apply_commands().await;
+}
+
+/// The default [`ErrorHandler`], see [`Schedule::set_error_handler`].
+fn log_error(system: &str, error: &Error) -> ErrorPolicy {
+    #[cfg(feature = "tracing")]
+    tracing::error!(system, %error, "system failed");
+    #[cfg(not(feature = "tracing"))]
+    eprintln!("system `{system}` failed: {error}");
+    ErrorPolicy::Continue
+}
+
+/// Applies the policy `handler` picks for the failed system named `name`, given the
+/// number of runs the system sits out and whether the schedule is stopped.
+fn handle_error(
+    handler: ErrorHandler,
+    name: &str,
+    error: &Error,
+    skipped: &mut u32,
+    stopped: &mut bool,
+) {
+    match handler(name, error) {
+        ErrorPolicy::Continue => {}
+        ErrorPolicy::Skip(runs) => *skipped = runs,
+        ErrorPolicy::Stop => *stopped = true,
+    }
+}
+
+/// A run of a [`Schedule`] made one system at a time, started by [`Schedule::step`].
+///
+/// Dropping it before [`next`](Stepper::next) returns `None` skips the remaining
+/// systems, and leaves the commands recorded so far queued on the world.
+pub struct Stepper<'a> {
+    // the systems that haven't run yet, along with the runs they sit out
+    systems: &'a [Arc<dyn System>],
+    skipped: &'a mut [u32],
+    handler: ErrorHandler,
+    stopped: &'a mut bool,
+    world: Arc<World>,
+    elapsed: Vec<(&'a str, Duration)>,
+    finished: bool,
+}
+impl<'a> Stepper<'a> {
+    /// Returns the name of the system the next step runs, or `None` if they all ran.
+    /// [Skipped](ErrorPolicy::Skip) systems are passed over.
+    pub fn peek(&self) -> Option<&'a str> {
+        let systems = self.systems.iter().zip(self.skipped.iter());
+        let mut systems = systems.filter(|(_, skipped)| **skipped == 0);
+        systems.next().map(|(system, _)| system.name())
+    }
+
+    /// Runs the next system and returns its name. Once every system ran, it finishes the
+    /// run instead, applying the recorded commands, and returns `None`.
+    ///
+    /// # Panics
+    /// Panics if the system panics.
+    pub async fn next(&mut self) -> Option<&'a str> {
+        loop {
+            let (Some((system, systems)), Some((skipped, rest))) = (
+                self.systems.split_first(),
+                std::mem::take(&mut self.skipped).split_first_mut(),
+            ) else {
+                if !self.finished {
+                    self.finished = true;
+                    finish_run(&self.world, std::mem::take(&mut self.elapsed)).await;
+                }
+                return None;
+            };
+            self.systems = systems;
+            self.skipped = rest;
+            if *skipped > 0 {
+                *skipped -= 1;
+                continue;
+            }
+            let start = Instant::now();
+            let result = system.run(&self.world).await;
+            self.elapsed.push((system.name(), start.elapsed()));
+            if let Err(error) = result {
+                handle_error(self.handler, system.name(), &error, skipped, self.stopped);
+            }
+            return Some(system.name());
+        }
+    }
+}
+
+/// How long the systems run against a world took, by system name. It is recorded by
+/// every [`Schedule`] run once it is inserted as a resource, so that frame spikes can
+/// be traced back to a system. The time of a system includes waiting for the entities
+/// and resources it locks, but not for the systems it conflicts with.
+/// ```rust
+/// use jest::{
+///     world::World,
+///     query::Query,
+///     systems::schedule::{Schedule, SystemTimings},
+/// };
+///
+/// struct Position(f32);
+///
+/// async fn movement(mut query: Query<&mut Position>) {
+///     let mut iter = query.iter().await;
+///     while let Some((_id, position)) = iter.next().await {
+///         position.0 += 1.0;
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     world.insert_resource(SystemTimings::default()).await;
+///
+///     let mut schedule = Schedule::new();
+///     schedule.add_system(movement);
+///     schedule.run(&world).await;
+///     schedule.run(&world).await;
+///
+///     let timings = world.get_resource::<SystemTimings>().await.unwrap();
+///     let (name, timing) = timings.iter().next().unwrap();
+///     assert!(name.ends_with("movement"));
+///     assert_eq!(timing.runs(), 2);
+///     assert!(timing.min() <= timing.average() && timing.average() <= timing.max());
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct SystemTimings {
+    systems: HashMap<String, SystemTiming>,
+}
+impl SystemTimings {
+    /// Returns the timing of the system named `name`, if it ran since this was inserted.
+    pub fn get(&self, name: &str) -> Option<&SystemTiming> {
+        self.systems.get(name)
+    }
+
+    /// Iterates over the names and timings of the systems that ran since this was
+    /// inserted, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &SystemTiming)> {
+        self.systems
+            .iter()
+            .map(|(name, timing)| (name.as_str(), timing))
+    }
+
+    /// Records that the system named `name` took `time` to run. Systems with the same
+    /// name share a timing.
+    fn record(&mut self, name: &str, time: Duration) {
+        match self.systems.get_mut(name) {
+            Some(timing) => {
+                timing.last = time;
+                timing.min = timing.min.min(time);
+                timing.max = timing.max.max(time);
+                timing.total += time;
+                timing.runs += 1;
+            }
+            None => {
+                let timing = SystemTiming {
+                    last: time,
+                    min: time,
+                    max: time,
+                    total: time,
+                    runs: 1,
+                };
+                self.systems.insert(name.to_owned(), timing);
+            }
+        }
+    }
+}
+impl Resource for SystemTimings {
+    const NAME: &'static str = "SystemTimings";
+}
+
+/// How long the runs of one system took, see [`SystemTimings`].
+#[derive(Debug, Clone, Copy)]
+pub struct SystemTiming {
+    last: Duration,
+    min: Duration,
+    max: Duration,
+    total: Duration,
+    runs: u64,
+}
+impl SystemTiming {
+    /// Returns how long the last run took.
+    pub fn last(&self) -> Duration {
+        self.last
+    }
+
+    /// Returns how long the shortest run took.
+    pub fn min(&self) -> Duration {
+        self.min
+    }
+
+    /// Returns how long the longest run took.
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Returns how long a run took on average.
+    pub fn average(&self) -> Duration {
+        self.total.div_f64(self.runs as f64)
+    }
+
+    /// Returns the number of runs recorded.
+    pub fn runs(&self) -> u64 {
+        self.runs
+    }
+}
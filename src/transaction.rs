@@ -0,0 +1,199 @@
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use tokio::sync::RwLock;
+
+use crate::entities::{component::Component, map::EntityMap, Entity, EntityId};
+
+/// Changes to components of several entities, applied all at once or not at all by
+/// [`World::commit`](crate::world::World::commit), or at the next sync point by
+/// [`Commands::commit`](crate::commands::Commands::commit).
+///
+/// Each update works on a copy of its component, so if any of them fails none of the
+/// copies are written back. The world is locked for the whole commit, so no other task
+/// can observe some of the changes without the others, as when trading items between
+/// two inventories.
+/// ```rust
+/// use jest::{
+///     world::World,
+///     entities::{builder::EntityBuilder, EntityId},
+///     transaction::Transaction,
+/// };
+///
+/// #[derive(Clone)]
+/// struct Gold(u32);
+///
+/// #[derive(Debug)]
+/// struct TooPoor;
+/// impl std::fmt::Display for TooPoor {
+///     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+///         write!(f, "not enough gold")
+///     }
+/// }
+/// impl std::error::Error for TooPoor {}
+///
+/// fn pay(from: EntityId, to: EntityId, amount: u32) -> Transaction {
+///     let mut transaction = Transaction::new();
+///     transaction
+///         .update(from, move |gold: &mut Gold| {
+///             gold.0 = gold.0.checked_sub(amount).ok_or(TooPoor)?;
+///             Ok::<_, TooPoor>(())
+///         })
+///         .update(to, move |gold: &mut Gold| {
+///             gold.0 += amount;
+///             Ok::<_, TooPoor>(())
+///         });
+///     transaction
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let world = World::new();
+///     let mut builder = EntityBuilder::new();
+///     builder.add(Gold(10)).unwrap();
+///     let buyer = builder.build(&world).await;
+///     let mut builder = EntityBuilder::new();
+///     builder.add(Gold(0)).unwrap();
+///     let seller = builder.build(&world).await;
+///
+///     world.commit(pay(buyer, seller, 7)).await.unwrap();
+///     // the second payment fails, so the seller isn't paid either
+///     assert!(world.commit(pay(buyer, seller, 7)).await.is_err());
+///     assert_eq!(world.get(buyer).await.unwrap().get::<Gold>().unwrap().0, 3);
+///     assert_eq!(world.get(seller).await.unwrap().get::<Gold>().unwrap().0, 7);
+/// }
+/// ```
+#[derive(Default)]
+pub struct Transaction {
+    updates: Vec<Update>,
+}
+
+/// An update of a [`Transaction`], applied to the copies staged so far.
+type Update = Box<dyn FnOnce(&mut Stage<'_>) -> Result<(), TransactionError> + Send>;
+
+impl Transaction {
+    /// Creates an empty transaction.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates the component of type `T` of the entity specified by `id` with `f`. The
+    /// whole transaction is rolled back if `f` returns an error, or if the entity
+    /// doesn't have such a component when the transaction is committed.
+    ///
+    /// Updates are applied in the order they were added, and later updates of the same
+    /// component see the changes of earlier ones.
+    pub fn update<T, E>(
+        &mut self,
+        id: EntityId,
+        f: impl FnOnce(&mut T) -> Result<(), E> + Send + 'static,
+    ) -> &mut Self
+    where
+        T: Component + Clone,
+        E: Error + Send + Sync + 'static,
+    {
+        self.updates.push(Box::new(move |stage| {
+            let component = stage.staged::<T>(id)?;
+            f(component).map_err(|error| TransactionError::Aborted(Box::new(error)))
+        }));
+        self
+    }
+
+    /// Applies the transaction to `map`, the entities of a world, which must be
+    /// write-locked.
+    pub(crate) fn apply(self, map: &mut EntityMap<RwLock<Entity>>) -> Result<(), TransactionError> {
+        let mut stage = Stage {
+            map,
+            staged: Vec::new(),
+            positions: HashMap::new(),
+        };
+        for update in self.updates {
+            update(&mut stage)?;
+        }
+        let Stage { map, staged, .. } = stage;
+        for (id, component, write) in staged {
+            write(map.get_mut(id).unwrap().get_mut(), component);
+        }
+        Ok(())
+    }
+}
+
+/// Writes a staged copy back into its entity.
+type Write = fn(&mut Entity, Box<dyn Component>);
+
+/// The copies of the components updated by a [`Transaction`] so far.
+struct Stage<'a> {
+    map: &'a mut EntityMap<RwLock<Entity>>,
+    // in the order they were first updated
+    staged: Vec<(EntityId, Box<dyn Component>, Write)>,
+    positions: HashMap<(EntityId, TypeId), usize>,
+}
+impl Stage<'_> {
+    /// Returns the copy of the component of type `T` of the entity specified by `id`,
+    /// copying it first if this is its first update.
+    fn staged<T: Component + Clone>(&mut self, id: EntityId) -> Result<&mut T, TransactionError> {
+        let position = match self.positions.get(&(id, TypeId::of::<T>())) {
+            Some(position) => *position,
+            None => {
+                let component = self
+                    .map
+                    .get_mut(id)
+                    .and_then(|entity| entity.get_mut().get::<T>())
+                    .ok_or(TransactionError::Missing {
+                        component: type_name::<T>(),
+                        entity: id,
+                    })?
+                    .clone();
+                let write: Write = |entity, component| {
+                    let component: Box<dyn Any> = component;
+                    *entity.get_mut::<T>().unwrap() = *component.downcast().unwrap();
+                };
+                self.staged.push((id, Box::new(component), write));
+                self.positions
+                    .insert((id, TypeId::of::<T>()), self.staged.len() - 1);
+                self.staged.len() - 1
+            }
+        };
+        let component: &mut dyn Any = &mut *self.staged[position].1;
+        Ok(component.downcast_mut().unwrap())
+    }
+}
+
+/// Error type returned from [`World::commit`](crate::world::World::commit)
+/// The transaction was rolled back, and none of its changes were made.
+#[derive(Debug)]
+pub enum TransactionError {
+    /// An update targeted a component that the entity doesn't have, or an entity that
+    /// isn't in the world.
+    Missing {
+        /// The type name of the component, as given by [`type_name`].
+        component: &'static str,
+        /// The entity the component was looked up on.
+        entity: EntityId,
+    },
+    /// An update returned this error.
+    Aborted(Box<dyn Error + Send + Sync>),
+}
+impl Display for TransactionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionError::Missing { component, entity } => write!(
+                f,
+                "transaction rolled back, entity {entity} has no component `{component}`"
+            ),
+            TransactionError::Aborted(error) => write!(f, "transaction rolled back: {error}"),
+        }
+    }
+}
+impl Error for TransactionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TransactionError::Missing { .. } => None,
+            TransactionError::Aborted(error) => Some(&**error),
+        }
+    }
+}
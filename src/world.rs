@@ -1,11 +1,43 @@
-use std::{cell::UnsafeCell, sync::Arc};
+use std::{
+    any::{type_name, Any, TypeId},
+    cell::UnsafeCell,
+    collections::HashMap,
+    sync::{
+        self,
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
-use slotmap::HopSlotMap;
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, RwLockReadGuard};
 
-use crate::entities::{Entity, EntityId, EntityMut, EntityRef};
+#[cfg(feature = "serde")]
+use crate::serialization::{self, SavedEntity};
+use crate::{
+    archetype::{Archetype, Column},
+    commands::Command,
+    entities::{
+        component::{self, Component, ComponentInfo, ComponentOptions, StorageType},
+        errors::VersionMismatch,
+        fetch::Fetch,
+        map::{EntityMap, InsertAtError},
+        Entity, EntityId, EntityMut, EntityRef,
+    },
+    events::{self, EventUpdater, Events},
+    hierarchy,
+    indexes::{Indexed, ValueIndex},
+    query::Query,
+    relations::{Relation, RelationIndex},
+    resources::{Resource, ResourceError, ResourceMut, ResourceRef, Resources},
+    systems,
+    transaction::{Transaction, TransactionError},
+};
 
-/// A world is a collection of [entities](Entity). It manages important
+/// Clones a type-erased component of a known type.
+pub(crate) type Cloner = fn(&dyn Component) -> Box<dyn Component>;
+
+/// A world is a collection of [entities](Entity), along with resources: global data
+/// that doesn't belong to any entity, at most one of each type. It manages important
 /// ECS functions, such as queries and systems, and it is the center of your game.
 /// # Usage
 /// Check the docs of [`Entity`] for some examples on how to use a world.
@@ -13,37 +45,294 @@ use crate::entities::{Entity, EntityId, EntityMut, EntityRef};
 /// # Performance
 /// Our `World` implementation is designed to be O(1) in every aspect.
 /// It is also designed to scale well to multiple threads.
+///
+/// Components are stored in archetypes: every set of component types that some entity
+/// has gets its own table, with one contiguous column per type and one row per entity.
+/// Accessing a component doesn't involve any hashing, and queries only visit the
+/// archetypes that have the components they ask for. In exchange, adding or removing a
+/// component moves the entity's other components to a different archetype. Components
+/// that are added and removed often can opt out of this with
+/// [`StorageType::SparseSet`](crate::entities::component::StorageType::SparseSet).
 pub struct World {
-    entities: UnsafeCell<HopSlotMap<EntityId, RwLock<Entity>>>,
+    entities: UnsafeCell<EntityMap<RwLock<Entity>>>,
     outer: RwLock<()>,
+    // every archetype, by its sorted component types
+    archetypes: sync::RwLock<HashMap<Vec<TypeId>, Arc<Archetype>>>,
+    // the single-column storage of every sparse component type
+    sparse_sets: sync::RwLock<HashMap<TypeId, Arc<Archetype>>>,
+    resources: Resources,
+    pub(crate) cloners: sync::RwLock<HashMap<TypeId, Cloner>>,
+    pub(crate) components: sync::RwLock<HashMap<TypeId, ComponentInfo>>,
+    pub(crate) commands: sync::Mutex<Vec<Command>>,
+    // shared with the `Events` resources, which remember when each event was sent
+    change_tick: Arc<AtomicU64>,
+    pub(crate) last_change_tick: AtomicU64,
+    // the entities components were removed from, along with the tick of each removal
+    removed: sync::Mutex<HashMap<TypeId, Vec<(EntityId, u64)>>>,
+    events: sync::Mutex<HashMap<TypeId, EventUpdater>>,
+    pub(crate) relations: sync::RwLock<HashMap<TypeId, RelationIndex>>,
+    pub(crate) indexes: sync::RwLock<HashMap<TypeId, ValueIndex>>,
+    #[cfg(feature = "serde")]
+    pub(crate) serializables: sync::RwLock<serialization::Registry>,
 }
 impl World {
     /// Creates a new, empty world.
     pub fn new() -> Arc<Self> {
-        Arc::new(Self {
-            entities: UnsafeCell::new(HopSlotMap::with_key()),
+        let world = Self {
+            entities: UnsafeCell::new(EntityMap::default()),
             outer: RwLock::new(()),
-        })
+            archetypes: sync::RwLock::new(HashMap::new()),
+            sparse_sets: sync::RwLock::new(HashMap::new()),
+            resources: Resources::new(),
+            cloners: sync::RwLock::new(HashMap::new()),
+            components: sync::RwLock::new(component::derived_options().collect()),
+            commands: sync::Mutex::new(Vec::new()),
+            change_tick: Arc::new(AtomicU64::new(1)),
+            last_change_tick: AtomicU64::new(0),
+            removed: sync::Mutex::new(HashMap::new()),
+            events: sync::Mutex::new(HashMap::new()),
+            relations: sync::RwLock::new(HashMap::new()),
+            indexes: sync::RwLock::new(HashMap::new()),
+            #[cfg(feature = "serde")]
+            serializables: sync::RwLock::new(serialization::Registry::default()),
+        };
+        // the hierarchy is saved along with the entities it links
+        #[cfg(feature = "serde")]
+        {
+            world.register_serializable::<hierarchy::Parent>();
+            world.register_serializable::<hierarchy::Children>();
+        }
+        Arc::new(world)
+    }
+
+    /// Registers `T` as cloneable, allowing components of type `T` to be copied out of
+    /// existing entities, such as by [`EntityBuilder::from_entity`](crate::entities::builder::EntityBuilder::from_entity).
+    pub fn register_clone<T: Component + Clone>(&self) {
+        self.cloners
+            .write()
+            .unwrap()
+            .insert(TypeId::of::<T>(), |component| {
+                let component: &dyn Any = component;
+                Box::new(component.downcast_ref::<T>().unwrap().clone())
+            });
+    }
+
+    /// Registers the [options](ComponentOptions) of the component type `T`, such as its
+    /// hooks. They apply to components of type `T` added from then on, while the
+    /// components already stored are left as they are, so plugins loaded after startup
+    /// can register their types into a live world. Options derived for a type that
+    /// isn't generic are already registered with every world created after the type was
+    /// linked in.
+    ///
+    /// # Panics
+    /// Panics if components of type `T` have already been stored with a different
+    /// [storage type](ComponentOptions::STORAGE), as queries would miss them.
+    pub fn register_component<T: ComponentOptions>(&self) {
+        let id = TypeId::of::<T>();
+        let info = ComponentInfo::of::<T>();
+        let stored_elsewhere = match info.storage {
+            StorageType::Table => self.sparse_sets.read().unwrap().contains_key(&id),
+            StorageType::SparseSet => self
+                .archetypes
+                .read()
+                .unwrap()
+                .keys()
+                .any(|types| types.contains(&id)),
+        };
+        assert!(
+            !stored_elsewhere,
+            "component `{}` can't change its storage type, as it is already in use",
+            type_name::<T>()
+        );
+        self.components.write().unwrap().insert(id, info);
+    }
+
+    /// Returns the type names of the component types registered with
+    /// [`register_component`](World::register_component), or by deriving their options,
+    /// sorted.
+    pub fn component_names(&self) -> Vec<&'static str> {
+        let mut names: Vec<_> = self
+            .components
+            .read()
+            .unwrap()
+            .values()
+            .map(|info| info.name)
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Registers `R` as a [`Relation`], indexing components of type `R` added from then on.
+    pub fn register_relation<R: Relation>(&self) {
+        self.relations
+            .write()
+            .unwrap()
+            .entry(TypeId::of::<R>())
+            .or_insert_with(RelationIndex::of::<R>);
+    }
+
+    /// Registers `T` as [`Indexed`], indexing components of type `T` added from then on
+    /// by their key.
+    pub fn register_index<T: Indexed>(&self) {
+        self.indexes
+            .write()
+            .unwrap()
+            .entry(TypeId::of::<T>())
+            .or_insert_with(ValueIndex::of::<T>);
+    }
+
+    /// Registers `T` as serializable, so that components of type `T` are written by
+    /// [`save`](World::save) and read by [`load`](World::load) and by
+    /// [scenes](crate::scene::Scene). They are stored under the
+    /// [type name](std::any::type_name) of `T`, which should be unique among the
+    /// registered types and is only guaranteed to be stable for a given build.
+    #[cfg(feature = "serde")]
+    pub fn register_serializable<T: Component + serde::Serialize + serde::de::DeserializeOwned>(
+        &self,
+    ) {
+        self.serializables.write().unwrap().register::<T>();
+    }
+
+    /// Returns the entities with a relation of type `R` to the entity specified by
+    /// `target`, in the order they were related to it. `R` must have been registered
+    /// with [`register_relation`](World::register_relation).
+    pub fn relations<R: Relation>(&self, target: EntityId) -> Vec<EntityId> {
+        self.relations
+            .read()
+            .unwrap()
+            .get(&TypeId::of::<R>())
+            .map_or_else(Vec::new, |index| index.sources(target).to_vec())
+    }
+
+    /// Returns the entities with a component of type `T` whose key is `key`, in no
+    /// particular order. `T` must have been registered with
+    /// [`register_index`](World::register_index).
+    ///
+    /// The entities whose component was borrowed mutably since the last lookup are
+    /// indexed again first, which waits for them to be unlocked. Within a system, this
+    /// panics if one of them is locked by a query of the system, as it would deadlock.
+    pub async fn lookup<T: Indexed>(&self, key: &T::Key) -> Vec<EntityId> {
+        let (_outer, entities) = self.entities().await;
+        let Some(dirty) = self
+            .indexes
+            .write()
+            .unwrap()
+            .get_mut(&TypeId::of::<T>())
+            .map(ValueIndex::dirty)
+        else {
+            return Vec::new();
+        };
+        for id in dirty {
+            let Some(entity) = entities.get(id) else {
+                continue;
+            };
+            let _claim = systems::claim(id);
+            entity.read().await.reindex(TypeId::of::<T>());
+        }
+        self.indexes.read().unwrap()[&TypeId::of::<T>()]
+            .entities::<T>(key)
+            .to_vec()
     }
 
     /// Inserts an entity into the world. Use this if you already have an [`Entity`] object.
     /// Otherwise, use [`EntityBuilder`](crate::entities::builder::EntityBuilder) to create one.
-    pub async fn insert(&self, entity: Entity) -> EntityId {
+    pub async fn insert(&self, mut entity: Entity) -> EntityId {
         let _outer = self.outer.write().await;
-        unsafe { &mut *self.entities.get() }.insert(RwLock::new(entity))
+        let id = unsafe { &mut *self.entities.get() }.insert_with_key(|id| {
+            entity.id = id;
+            entity.attach();
+            entity.spawned();
+            RwLock::new(entity)
+        });
+        #[cfg(feature = "tracing")]
+        tracing::debug!(entity = ?id, "entity spawned");
+        id
+    }
+
+    /// Inserts several entities under a single lock, returning their IDs in order.
+    pub(crate) async fn insert_many(&self, entities: Vec<Entity>) -> Vec<EntityId> {
+        let _outer = self.outer.write().await;
+        let map = unsafe { &mut *self.entities.get() };
+        entities
+            .into_iter()
+            .map(|mut entity| {
+                let id = map.insert_with_key(|id| {
+                    entity.id = id;
+                    entity.attach();
+                    entity.spawned();
+                    RwLock::new(entity)
+                });
+                #[cfg(feature = "tracing")]
+                tracing::debug!(entity = ?id, "entity spawned");
+                id
+            })
+            .collect()
     }
 
     /// Removes an entity from the world by ID. Returns the entity if it existed.
+    ///
+    /// The entity is taken out of its [hierarchy](crate::hierarchy): it is removed from the
+    /// children of its parent, and its children lose their parent. Use
+    /// [`despawn_recursive`](World::despawn_recursive) to remove the children as well.
     pub async fn remove(&self, id: EntityId) -> Option<Entity> {
         let _outer = self.outer.write().await;
-        unsafe { &mut *self.entities.get() }
-            .remove(id)
-            .map(|e| e.into_inner())
+        self.remove_from(unsafe { &mut *self.entities.get() }, id)
+    }
+
+    /// Removes an entity along with all of its descendants, returning them with parents
+    /// before their children. Nothing is removed if the entity doesn't exist.
+    /// ```rust
+    /// use jest::{world::World, entities::builder::EntityBuilder};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///     let tank = EntityBuilder::new().build(&world).await;
+    ///     let turret = EntityBuilder::new().build(&world).await;
+    ///     let barrel = EntityBuilder::new().build(&world).await;
+    ///     world.get_mut(tank).await.unwrap().add_child(turret).await.unwrap();
+    ///     world.get_mut(turret).await.unwrap().add_child(barrel).await.unwrap();
+    ///
+    ///     assert_eq!(world.despawn_recursive(tank).await.len(), 3);
+    ///     assert!(world.get(barrel).await.is_none());
+    /// }
+    /// ```
+    pub async fn despawn_recursive(&self, id: EntityId) -> Vec<Entity> {
+        let _outer = self.outer.write().await;
+        let map = unsafe { &mut *self.entities.get() };
+        hierarchy::subtree(map, id)
+            .into_iter()
+            .filter_map(|id| self.remove_from(map, id))
+            .collect()
+    }
+
+    /// Removes an entity from `map`, the entities of this world, which must be write-locked.
+    /// Relations pointing to it are removed from their sources.
+    fn remove_from(&self, map: &mut EntityMap<RwLock<Entity>>, id: EntityId) -> Option<Entity> {
+        let mut entity = map.remove(id)?.into_inner();
+        hierarchy::unlink(map, &mut entity);
+        entity.detach();
+        entity.despawned();
+        let related: Vec<_> = self
+            .relations
+            .read()
+            .unwrap()
+            .iter()
+            .flat_map(|(ty, index)| index.sources(id).iter().map(|source| (*ty, *source)))
+            .collect();
+        for (ty, source) in related {
+            if let Some(source) = map.get_mut(source) {
+                source.get_mut().remove_dyn(ty);
+            }
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(entity = ?id, "entity despawned");
+        Some(entity)
     }
 
     /// Gets an immutable reference to the entity specified by `id`.
     /// See the docs of [`EntityRef`] for more information.
-    pub async fn get(&self, id: EntityId) -> Option<EntityRef> {
+    pub async fn get(&self, id: EntityId) -> Option<EntityRef<'_>> {
         let _outer = self.outer.read().await;
         let inner = unsafe { &*self.entities.get() }.get(id)?;
         Some(EntityRef {
@@ -54,7 +343,7 @@ impl World {
 
     /// Gets a mutable reference to the entity specified by `id`.
     /// See the docs of [`EntityMut`] for more information.
-    pub async fn get_mut(&self, id: EntityId) -> Option<EntityMut> {
+    pub async fn get_mut(&self, id: EntityId) -> Option<EntityMut<'_>> {
         let _outer = self.outer.read().await;
         let inner = unsafe { &*self.entities.get() }.get(id)?;
         Some(EntityMut {
@@ -62,6 +351,591 @@ impl World {
             inner: inner.write().await,
         })
     }
+
+    /// Gets a mutable reference to the entity specified by `id`, spawning an empty entity
+    /// with that very ID if there is none. This lets updates keyed by ID, such as ones
+    /// received from another world, be applied whether or not the entity was created yet.
+    ///
+    /// Returns `None` if the ID can't be spawned at: if another entity uses its slot, or
+    /// if the ID is older than one the slot already had, which is the case for the ID of
+    /// an entity that was removed.
+    /// ```rust
+    /// use jest::{world::World, entities::EntityId};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///     let id: EntityId = "5:1".parse().unwrap();
+    ///     world.get_or_spawn(id).await.unwrap().add(7u32).unwrap();
+    ///     assert_eq!(*world.get_or_spawn(id).await.unwrap().get::<u32>().unwrap(), 7);
+    ///
+    ///     world.remove(id).await;
+    ///     assert!(world.get_or_spawn(id).await.is_none());
+    /// }
+    /// ```
+    pub async fn get_or_spawn(self: &Arc<Self>, id: EntityId) -> Option<EntityMut<'_>> {
+        if let Some(entity) = self.get_mut(id).await {
+            return Some(entity);
+        }
+        let outer = self.outer.write().await;
+        let spawned = unsafe { &mut *self.entities.get() }.insert_at(id, || {
+            let mut entity = Entity::new(HashMap::new(), self.clone());
+            entity.id = id;
+            entity.attach();
+            entity.spawned();
+            RwLock::new(entity)
+        });
+        match spawned {
+            Ok(()) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(entity = ?id, "entity spawned");
+            }
+            // it was spawned since it was looked up
+            Err(InsertAtError::Occupied) => {}
+            Err(_) => return None,
+        }
+        let _outer = outer.downgrade();
+        let inner = unsafe { &*self.entities.get() }.get(id)?;
+        Some(EntityMut {
+            _outer,
+            inner: inner.write().await,
+        })
+    }
+
+    /// Replaces the component of type `T` on the entity specified by `id` with `value`,
+    /// but only if the component's [version](Entity::version) is still `expected`.
+    /// Returns the component's new version, or [`VersionMismatch`] if it was modified or
+    /// removed in the meantime.
+    ///
+    /// This lets a task read a component, compute an update without holding any guard
+    /// (for example across an `.await`), and then apply it only if nothing else changed it.
+    /// ```rust
+    /// use jest::{world::World, entities::builder::EntityBuilder};
+    ///
+    /// struct Gold(u32);
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///     let mut builder = EntityBuilder::new();
+    ///     builder.add(Gold(10)).unwrap();
+    ///     let entity_id = builder.build(&world).await;
+    ///
+    ///     let (gold, version) = {
+    ///         let entity = world.get(entity_id).await.unwrap();
+    ///         (entity.get::<Gold>().unwrap().0, entity.version::<Gold>().unwrap())
+    ///     };
+    ///
+    ///     // someone else changes the component in the meantime
+    ///     world.get_mut(entity_id).await.unwrap().get_mut::<Gold>().unwrap().0 += 5;
+    ///
+    ///     let stale = world.compare_and_update(entity_id, version, Gold(gold + 1)).await;
+    ///     let current = stale.unwrap_err().current.unwrap();
+    ///
+    ///     // retrying against the current version succeeds
+    ///     let updated = world.compare_and_update(entity_id, current, Gold(16)).await;
+    ///     assert!(updated.unwrap() > current);
+    /// }
+    /// ```
+    pub async fn compare_and_update<T: Component>(
+        &self,
+        id: EntityId,
+        expected: u64,
+        value: T,
+    ) -> Result<u64, VersionMismatch<T>> {
+        let Some(mut entity) = self.get_mut(id).await else {
+            return Err(VersionMismatch {
+                value,
+                current: None,
+            });
+        };
+        match entity.version::<T>() {
+            Some(current) if current == expected => {
+                entity.replace(value);
+                Ok(entity.version::<T>().unwrap())
+            }
+            current => Err(VersionMismatch { value, current }),
+        }
+    }
+
+    /// Applies every update of `transaction`, or none of them if one fails. The world is
+    /// locked for writing meanwhile, so the changes are seen all at once. See
+    /// [`Transaction`] for an example.
+    pub async fn commit(&self, transaction: Transaction) -> Result<(), TransactionError> {
+        let _outer = self.outer.write().await;
+        transaction.apply(unsafe { &mut *self.entities.get() })
+    }
+
+    /// Applies every command queued by dropped [`Commands`](crate::commands::Commands)
+    /// buffers, in the order they were queued. A [`Schedule`](crate::systems::schedule::Schedule)
+    /// calls this after running its systems, so it's only needed for commands recorded
+    /// elsewhere.
+    pub async fn apply_commands(self: &Arc<Self>) {
+        let commands = std::mem::take(&mut *self.commands.lock().unwrap());
+        for command in commands {
+            command.apply(self).await;
+        }
+    }
+
+    /// Inserts a resource into the world, returning the resource of the same type it
+    /// replaced, if any. Resources don't need to be [registered](World::register_resource)
+    /// to be inserted.
+    /// ```rust
+    /// use jest::{world::World, resources::Resource};
+    ///
+    /// #[derive(Resource)]
+    /// struct Score(u32);
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///     assert!(world.insert_resource(Score(0)).await.is_none());
+    ///
+    ///     world.get_resource_mut::<Score>().await.unwrap().0 += 10;
+    ///     assert_eq!(world.get_resource::<Score>().await.unwrap().0, 10);
+    ///
+    ///     assert_eq!(world.remove_resource::<Score>().await.unwrap().0, 10);
+    ///     assert!(world.get_resource::<Score>().await.is_err());
+    /// }
+    /// ```
+    pub async fn insert_resource<T: Resource>(&self, resource: T) -> Option<T> {
+        self.resources.insert(resource).await
+    }
+
+    /// Registers `T` as a [`Resource`], listing it in [`resource_names`](World::resource_names)
+    /// and inserting its [initial value](Resource::initial) if the world doesn't have a
+    /// resource of type `T` yet.
+    ///
+    /// # Panics
+    /// Panics if another registered resource type has the same [name](Resource::NAME).
+    pub async fn register_resource<T: Resource>(&self) {
+        self.resources.register::<T>().await;
+    }
+
+    /// Returns the names of the resource types registered with
+    /// [`register_resource`](World::register_resource), sorted, whether or not the world
+    /// currently has them.
+    pub fn resource_names(&self) -> Vec<&'static str> {
+        self.resources.names()
+    }
+
+    /// Removes the resource of type `T` from the world. Returns the resource if it existed.
+    pub async fn remove_resource<T: Resource>(&self) -> Option<T> {
+        self.resources.remove().await
+    }
+
+    /// Gets an immutable reference to the resource of type `T`, or a [`ResourceError`]
+    /// naming it if the world doesn't have it.
+    /// See the docs of [`ResourceRef`] for more information.
+    pub async fn get_resource<T: Resource>(&self) -> Result<ResourceRef<'_, T>, ResourceError> {
+        let resource = self.resources.get().await;
+        resource.ok_or_else(|| self.resources.missing::<T>())
+    }
+
+    /// Gets a mutable reference to the resource of type `T`, or a [`ResourceError`]
+    /// naming it if the world doesn't have it.
+    /// See the docs of [`ResourceMut`] for more information.
+    pub async fn get_resource_mut<T: Resource>(&self) -> Result<ResourceMut<'_, T>, ResourceError> {
+        let resource = self.resources.get_mut().await;
+        resource.ok_or_else(|| self.resources.missing::<T>())
+    }
+
+    /// Adds the [`Events`] resource for events of type `T`, unless the world already
+    /// has it. Every [`Schedule`](crate::systems::schedule::Schedule) run then updates it,
+    /// dropping the events that every system had the chance to read.
+    /// [`EventWriter`](crate::events::EventWriter) adds it when the first event is sent.
+    pub async fn add_event<T: Any + Send + Sync>(&self) {
+        let change_tick = self.change_tick.clone();
+        if self
+            .resources
+            .insert_if_absent(|| Events::<T>::new(change_tick))
+            .await
+        {
+            self.events
+                .lock()
+                .unwrap()
+                .insert(TypeId::of::<T>(), events::update::<T>);
+        }
+    }
+
+    /// Updates the [`Events`] of every type added to the world, see [`World::add_event`].
+    pub(crate) async fn update_events(&self) {
+        let updaters: Vec<_> = self.events.lock().unwrap().values().copied().collect();
+        for update in updaters {
+            update(self).await;
+        }
+    }
+
+    /// Creates a [`Query`] over every entity that has the components requested by `Q`.
+    /// See the docs of [`Query`] for more information.
+    ///
+    /// Outside of a system, [`Added`](crate::query::Added) and [`Changed`](crate::query::Changed)
+    /// detect changes made since the last [`last_change_tick`](World::last_change_tick).
+    pub fn query<Q: Fetch>(self: &Arc<Self>) -> Query<Q> {
+        Query::new(self.clone(), self.last_change_tick(), self.change_tick())
+    }
+
+    /// Returns the current change tick of the world.
+    ///
+    /// The tick advances whenever a [`Schedule`](crate::systems::schedule::Schedule)
+    /// run or a system starts. Components remember the ticks they were added and last
+    /// changed at, which [`Added`](crate::query::Added) and
+    /// [`Changed`](crate::query::Changed) compare to the tick the querying system last
+    /// ran at.
+    pub fn change_tick(&self) -> u64 {
+        self.change_tick.load(Ordering::Acquire)
+    }
+
+    /// Advances the change tick, returning its previous value.
+    pub(crate) fn increment_change_tick(&self) -> u64 {
+        self.change_tick.fetch_add(1, Ordering::AcqRel)
+    }
+
+    /// Returns the IDs of the entities a component of type `T` was removed from, or that
+    /// were despawned with one, since the [`last_change_tick`](World::last_change_tick).
+    /// Systems should take [`RemovedComponents`](crate::removal::RemovedComponents) instead.
+    /// ```rust
+    /// use jest::{world::World, entities::builder::EntityBuilder};
+    ///
+    /// struct Collider;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///     let mut builder = EntityBuilder::new();
+    ///     builder.add(Collider).unwrap();
+    ///     let entity_id = builder.build(&world).await;
+    ///
+    ///     world.get_mut(entity_id).await.unwrap().remove::<Collider>();
+    ///     assert_eq!(world.removed::<Collider>().collect::<Vec<_>>(), [entity_id]);
+    /// }
+    /// ```
+    pub fn removed<T: Component>(&self) -> impl Iterator<Item = EntityId> {
+        self.removed_since(TypeId::of::<T>(), self.last_change_tick())
+            .into_iter()
+    }
+
+    /// Records that the component with type ID `id` was removed from `entity`.
+    pub(crate) fn record_removal(&self, id: TypeId, entity: EntityId) {
+        let tick = self.change_tick();
+        self.removed
+            .lock()
+            .unwrap()
+            .entry(id)
+            .or_default()
+            .push((entity, tick));
+    }
+
+    /// Returns the entities the component with type ID `id` was removed from after the
+    /// change tick `last_run`.
+    pub(crate) fn removed_since(&self, id: TypeId, last_run: u64) -> Vec<EntityId> {
+        self.removed
+            .lock()
+            .unwrap()
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .filter(|(_, tick)| *tick > last_run)
+            .map(|(entity, _)| *entity)
+            .collect()
+    }
+
+    /// Forgets the removals made at or before the change tick `tick`.
+    pub(crate) fn clear_removed(&self, tick: u64) {
+        self.removed.lock().unwrap().retain(|_, removed| {
+            removed.retain(|(_, removed)| *removed > tick);
+            !removed.is_empty()
+        });
+    }
+
+    /// Returns the change tick at which the last [`Schedule`](crate::systems::schedule::Schedule)
+    /// run started, or 0 if there was none. Changes are detected relative to it outside
+    /// of systems.
+    pub fn last_change_tick(&self) -> u64 {
+        self.last_change_tick.load(Ordering::Acquire)
+    }
+
+    /// Serializes every entity of the world, along with its ID, as a sequence of
+    /// `{ id, components }` structs. Components are written as a map from type names to
+    /// values, and only those of the types registered with
+    /// [`register_serializable`](World::register_serializable) are included, along with
+    /// the [`Parent`](hierarchy::Parent) and [`Children`](hierarchy::Children) components,
+    /// which are always registered. Resources aren't serialized.
+    /// ```rust
+    /// use jest::{world::World, entities::builder::EntityBuilder};
+    /// use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    ///
+    /// struct Health(u32);
+    /// impl Serialize for Health {
+    ///     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    ///         self.0.serialize(serializer)
+    ///     }
+    /// }
+    /// impl<'de> Deserialize<'de> for Health {
+    ///     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    ///         u32::deserialize(deserializer).map(Health)
+    ///     }
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///     world.register_serializable::<Health>();
+    ///     let mut builder = EntityBuilder::new();
+    ///     builder.add(Health(10)).unwrap();
+    ///     let player = builder.build(&world).await;
+    ///
+    ///     let json = world.save(serde_json::value::Serializer).await.unwrap();
+    ///
+    ///     let restored = World::new();
+    ///     restored.register_serializable::<Health>();
+    ///     restored.load(json).await.unwrap();
+    ///     assert_eq!(restored.get(player).await.unwrap().get::<Health>().unwrap().0, 10);
+    /// }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub async fn save<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (outer, map) = self.entities().await;
+        let mut saved = Vec::with_capacity(map.len());
+        for (id, entity) in map.iter() {
+            let entity = entity.read().await;
+            let components = self.serializables.read().unwrap().serialize(&entity);
+            saved.push(SavedEntity {
+                id,
+                components: components.map_err(serde::ser::Error::custom)?,
+            });
+        }
+        drop(outer);
+        serializer.collect_seq(saved)
+    }
+
+    /// Replaces every entity of the world with the ones deserialized from `deserializer`,
+    /// in the form written by [`save`](World::save). The entities keep the IDs they were
+    /// saved with, so components referring to them stay valid. The format must be
+    /// self-describing, and every component type must be registered with
+    /// [`register_serializable`](World::register_serializable); otherwise, an error is
+    /// returned and the world is left untouched. The same goes for saved
+    /// [`Parent`](hierarchy::Parent)s and [`Children`](hierarchy::Children) that don't
+    /// match each other.
+    ///
+    /// The world gets a slot for every index up to the highest saved one, and the slots
+    /// no entity was saved at are left free, so IDs of entities that were despawned
+    /// before saving may be handed out again afterwards.
+    #[cfg(feature = "serde")]
+    pub async fn load<'de, D: serde::Deserializer<'de>>(
+        self: &Arc<Self>,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        use serde::de::{Deserialize, Error};
+
+        let saved = Vec::<SavedEntity>::deserialize(deserializer)?;
+        let entities = {
+            let registry = self.serializables.read().unwrap();
+            saved
+                .into_iter()
+                .map(|saved| {
+                    registry.deserialize(saved.components).map(|components| {
+                        (saved.id, RwLock::new(Entity::new(components, self.clone())))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(D::Error::custom)?
+        };
+        let mut restored = serialization::restore(entities).map_err(D::Error::custom)?;
+        hierarchy::check(&mut restored).map_err(D::Error::custom)?;
+
+        let _outer = self.outer.write().await;
+        let map = unsafe { &mut *self.entities.get() };
+        for id in map.keys().collect::<Vec<_>>() {
+            self.remove_from(map, id);
+        }
+        for (id, entity) in restored.iter_mut() {
+            let entity = entity.get_mut();
+            entity.id = id;
+            entity.attach();
+            entity.spawned();
+        }
+        *map = restored;
+        Ok(())
+    }
+
+    /// Deserializes an entity in the form written by its `Serialize` implementation: a
+    /// map from type names to components, whose types must all be registered with
+    /// [`register_serializable`](World::register_serializable). The entity isn't
+    /// inserted into the world; use [`insert`](World::insert) to do so. An entity can't
+    /// have a [`Parent`](hierarchy::Parent) or [`Children`](hierarchy::Children) on its
+    /// own, so those are rejected.
+    #[cfg(feature = "serde")]
+    pub fn deserialize_entity<'de, D: serde::Deserializer<'de>>(
+        self: &Arc<Self>,
+        deserializer: D,
+    ) -> Result<Entity, D::Error> {
+        use serde::de::{Deserialize, Error};
+
+        let components = serialization::Components::deserialize(deserializer)?;
+        let components = self
+            .serializables
+            .read()
+            .unwrap()
+            .deserialize(components)
+            .map_err(D::Error::custom)?;
+        if components.keys().any(|id| hierarchy::is_link(*id)) {
+            return Err(D::Error::custom(
+                "the hierarchy can only be deserialized along with the whole world",
+            ));
+        }
+        Ok(Entity::new(components, self.clone()))
+    }
+
+    /// Returns the lock on the entity specified by `id`.
+    ///
+    /// # Safety
+    /// The world must be read-locked for as long as the reference lives.
+    pub(crate) unsafe fn entity(&self, id: EntityId) -> Option<&RwLock<Entity>> {
+        (*self.entities.get()).get(id)
+    }
+
+    /// Read-locks the world, returning the guard along with its entities.
+    pub(crate) async fn entities(&self) -> (RwLockReadGuard<'_, ()>, &EntityMap<RwLock<Entity>>) {
+        let outer = self.outer.read().await;
+        (outer, unsafe { &*self.entities.get() })
+    }
+
+    /// Returns the archetype storing exactly the component types `types`, which must be
+    /// sorted. If there is none yet, it is created with the columns made by `columns`,
+    /// in the same order as `types`.
+    pub(crate) fn archetype(
+        &self,
+        types: &[TypeId],
+        columns: impl FnOnce() -> Vec<Box<dyn Column>>,
+    ) -> Arc<Archetype> {
+        if let Some(archetype) = self.archetypes.read().unwrap().get(types) {
+            return archetype.clone();
+        }
+        self.archetypes
+            .write()
+            .unwrap()
+            .entry(types.to_vec())
+            .or_insert_with(|| Arc::new(Archetype::new(types.to_vec(), columns())))
+            .clone()
+    }
+
+    /// Returns every archetype storing at least the component types `ids`.
+    pub(crate) fn archetypes_with(&self, ids: &[TypeId]) -> Vec<Arc<Archetype>> {
+        self.archetypes
+            .read()
+            .unwrap()
+            .values()
+            .filter(|archetype| archetype.contains_all(ids))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the sparse set storing components with type ID `id`. If there is none yet,
+    /// it is created with the column made by `column`.
+    pub(crate) fn sparse_set(
+        &self,
+        id: TypeId,
+        column: impl FnOnce() -> Box<dyn Column>,
+    ) -> Arc<Archetype> {
+        if let Some(set) = self.sparse_sets.read().unwrap().get(&id) {
+            return set.clone();
+        }
+        self.sparse_sets
+            .write()
+            .unwrap()
+            .entry(id)
+            .or_insert_with(|| Arc::new(Archetype::new(vec![id], vec![column()])))
+            .clone()
+    }
+
+    /// Returns the sparse set storing components with type ID `id`, if it has been created.
+    pub(crate) fn find_sparse_set(&self, id: TypeId) -> Option<Arc<Archetype>> {
+        self.sparse_sets.read().unwrap().get(&id).cloned()
+    }
+
+    /// Returns where components with type ID `id` are stored.
+    pub(crate) fn storage(&self, id: TypeId) -> StorageType {
+        self.components
+            .read()
+            .unwrap()
+            .get(&id)
+            .map_or(StorageType::Table, |info| info.storage)
+    }
+
+    /// Returns a cursor over every entity currently in the world. The IDs are collected
+    /// up front, and each entity is only read-locked when the cursor reaches it, so the
+    /// world stays unlocked between entities. Entities removed before they are reached
+    /// are skipped, and ones inserted after this call are not visited.
+    /// ```rust
+    /// use jest::{world::World, entities::builder::EntityBuilder};
+    ///
+    /// struct Health(u32);
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///     for health in [10, 20, 30] {
+    ///         let mut builder = EntityBuilder::new();
+    ///         builder.add(Health(health)).unwrap();
+    ///         builder.build(&world).await;
+    ///     }
+    ///
+    ///     let mut total = 0;
+    ///     let mut entities = world.iter_entities().await;
+    ///     while let Some((_id, entity)) = entities.next().await {
+    ///         total += entity.get::<Health>().unwrap().0;
+    ///     }
+    ///     assert_eq!(total, 60);
+    /// }
+    /// ```
+    pub async fn iter_entities(&self) -> Entities<'_> {
+        let _outer = self.outer.read().await;
+        Entities {
+            world: self,
+            ids: unsafe { &*self.entities.get() }
+                .keys()
+                .collect::<Vec<_>>()
+                .into_iter(),
+        }
+    }
+
+    /// Finds every entity for which `predicate` returns `true`, returning their IDs.
+    /// Each entity is read-locked while the predicate runs, so this will wait for
+    /// any outstanding [`EntityMut`]s to be dropped.
+    /// ```rust
+    /// use jest::{world::World, entities::builder::EntityBuilder};
+    ///
+    /// struct Name(&'static str);
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let world = World::new();
+    ///
+    ///     let mut builder = EntityBuilder::new();
+    ///     builder.add(Name("front door")).unwrap();
+    ///     let door = builder.build(&world).await;
+    ///
+    ///     let mut builder = EntityBuilder::new();
+    ///     builder.add(Name("player")).unwrap();
+    ///     builder.build(&world).await;
+    ///
+    ///     let doors = world
+    ///         .find(|entity| entity.get::<Name>().is_some_and(|name| name.0.contains("door")))
+    ///         .await;
+    ///     assert_eq!(doors, vec![door]);
+    /// }
+    /// ```
+    pub async fn find(&self, mut predicate: impl FnMut(&Entity) -> bool) -> Vec<EntityId> {
+        let _outer = self.outer.read().await;
+        let mut found = Vec::new();
+        for (id, entity) in unsafe { &*self.entities.get() }.iter() {
+            if predicate(&*entity.read().await) {
+                found.push(id);
+            }
+        }
+        found
+    }
 }
 
 unsafe impl Send for World {}
@@ -69,3 +943,21 @@ unsafe impl Send for World {}
 /// An interested reader can browse the source to understand the implementation
 /// details.
 unsafe impl Sync for World {}
+
+/// A cursor over the entities of a [`World`], returned by [`World::iter_entities`].
+pub struct Entities<'a> {
+    world: &'a World,
+    ids: std::vec::IntoIter<EntityId>,
+}
+impl<'a> Entities<'a> {
+    /// Read-locks the next entity that still exists and returns it along with its ID,
+    /// or `None` once every entity has been visited.
+    pub async fn next(&mut self) -> Option<(EntityId, EntityRef<'a>)> {
+        for id in self.ids.by_ref() {
+            if let Some(entity) = self.world.get(id).await {
+                return Some((id, entity));
+            }
+        }
+        None
+    }
+}
@@ -1,5 +1,3 @@
-
-
 mod common;
 
 #[test]
@@ -7,4 +5,1759 @@ fn dummy() {
     common::setup();
 
     // test code would go here
-}
\ No newline at end of file
+}
+
+struct Position(u32);
+struct Velocity(u32);
+
+#[tokio::test]
+async fn add_bundle_is_all_or_nothing() {
+    common::setup();
+
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add(Velocity(0)).unwrap();
+    assert!(builder.add_bundle((Position(1), Velocity(2))).is_err());
+    assert!(builder.add_bundle((Position(1), Position(2))).is_err());
+    builder.add_bundle((Position(3),)).unwrap();
+
+    let world = jest::world::World::new();
+    let entity_id = builder.build(&world).await;
+    let entity = world.get(entity_id).await.unwrap();
+    assert_eq!(entity.get::<Position>().unwrap().0, 3);
+    assert_eq!(entity.get::<Velocity>().unwrap().0, 0);
+}
+
+#[tokio::test]
+async fn nested_bundles_are_stored_as_one_component() {
+    common::setup();
+
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder
+        .add_bundle(((Position(1), Velocity(2)), Position(3)))
+        .unwrap();
+
+    let world = jest::world::World::new();
+    let entity_id = builder.build(&world).await;
+    let entity = world.get(entity_id).await.unwrap();
+    assert_eq!(entity.get::<Position>().unwrap().0, 3);
+    assert!(entity.get::<Velocity>().is_none());
+    let (position, velocity) = entity.get::<(Position, Velocity)>().unwrap();
+    assert_eq!((position.0, velocity.0), (1, 2));
+}
+
+#[tokio::test]
+async fn already_exists_names_component_and_entity() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add(Position(0)).unwrap();
+    let err = builder.add(Position(1)).err().unwrap();
+    assert_eq!(err.component, std::any::type_name::<Position>());
+    assert_eq!(err.entity, None);
+
+    let entity_id = builder.build(&world).await;
+    let mut entity = world.get_mut(entity_id).await.unwrap();
+    let err = entity.add(Position(2)).unwrap_err();
+    assert_eq!(err.entity, Some(entity_id));
+    assert!(err.to_string().contains("Position"));
+}
+
+#[tokio::test]
+#[should_panic(expected = "accessed mutably and more than once")]
+async fn get_many_rejects_aliasing() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add(Position(0)).unwrap();
+    let entity_id = builder.build(&world).await;
+    let mut entity = world.get_mut(entity_id).await.unwrap();
+    entity.get_many::<(&Position, &mut Position)>();
+}
+
+#[tokio::test]
+async fn query_visits_only_matching_entities() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add_bundle((Position(0), Velocity(2))).unwrap();
+    let moving = builder.build(&world).await;
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add(Position(5)).unwrap();
+    let still = builder.build(&world).await;
+
+    let mut query = world.query::<(&mut Position, &Velocity)>();
+    let mut iter = query.iter().await;
+    let mut visited = Vec::new();
+    while let Some((id, (position, velocity))) = iter.next().await {
+        position.0 += velocity.0;
+        visited.push(id);
+    }
+    drop(iter);
+    assert_eq!(visited, vec![moving]);
+
+    assert_eq!(
+        world
+            .get(moving)
+            .await
+            .unwrap()
+            .get::<Position>()
+            .unwrap()
+            .0,
+        2
+    );
+    assert_eq!(
+        world.get(still).await.unwrap().get::<Position>().unwrap().0,
+        5
+    );
+}
+
+#[tokio::test]
+async fn query_filter_or() {
+    use jest::query::{Or, With};
+    common::setup();
+
+    let world = jest::world::World::new();
+    for bundle in [
+        (Some(Position(0)), None),
+        (None, Some(Velocity(0))),
+        (None, None),
+    ] {
+        let mut builder = jest::entities::builder::EntityBuilder::new();
+        builder
+            .add_some(bundle.0)
+            .unwrap()
+            .add_some(bundle.1)
+            .unwrap();
+        builder.build(&world).await;
+    }
+
+    let mut query = world
+        .query::<()>()
+        .filter::<Or<(With<Position>, With<Velocity>)>>();
+    let mut iter = query.iter().await;
+    let mut matched = 0;
+    while iter.next().await.is_some() {
+        matched += 1;
+    }
+    assert_eq!(matched, 2);
+}
+
+#[tokio::test]
+async fn query_macro_expands_to_typed_query() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add_bundle((Position(1), Velocity(2))).unwrap();
+    let moving = builder.build(&world).await;
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add(Position(3)).unwrap();
+    let resting = builder.build(&world).await;
+
+    let mut query = jest::query!(world, (position: &Position), without Velocity);
+    let mut iter = query.iter().await;
+    let (id, (position,)) = iter.next().await.unwrap();
+    assert_eq!((id, position.0), (resting, 3));
+    assert!(iter.next().await.is_none());
+    drop(iter);
+
+    // an empty run moves the tick changes are detected relative to past the spawns
+    jest::systems::schedule::Schedule::new().run(&world).await;
+    world
+        .get_mut(moving)
+        .await
+        .unwrap()
+        .get_mut::<Position>()
+        .unwrap()
+        .0 = 5;
+    let mut query = jest::query!(world, (velocity: &Velocity), changed Position, with Position);
+    let mut iter = query.iter().await;
+    assert_eq!(iter.next().await.unwrap().0, moving);
+    assert!(iter.next().await.is_none());
+}
+
+async fn apply_velocity(mut query: jest::query::Query<(&mut Position, &Velocity)>) {
+    let mut iter = query.iter().await;
+    while let Some((_, (position, velocity))) = iter.next().await {
+        position.0 += velocity.0;
+    }
+}
+
+async fn double_position(
+    mut positions: jest::query::Query<&mut Position>,
+    _velocities: jest::query::Query<&Velocity>,
+) {
+    let mut iter = positions.iter().await;
+    while let Some((_, position)) = iter.next().await {
+        position.0 *= 2;
+    }
+}
+
+#[tokio::test]
+async fn schedule_runs_systems_in_order() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add_bundle((Position(1), Velocity(1))).unwrap();
+    let entity_id = builder.build(&world).await;
+
+    let mut schedule = jest::systems::schedule::Schedule::new();
+    schedule
+        .add_system(apply_velocity)
+        .add_system(double_position);
+    schedule.run(&world).await;
+
+    let entity = world.get(entity_id).await.unwrap();
+    assert_eq!(entity.get::<Position>().unwrap().0, 4);
+}
+
+#[tokio::test]
+async fn schedules_can_be_stepped_one_system_at_a_time() {
+    use jest::systems::schedule::{Schedule, SystemTimings};
+
+    common::setup();
+
+    let world = jest::world::World::new();
+    world.insert_resource(SystemTimings::default()).await;
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add_bundle((Position(1), Velocity(1))).unwrap();
+    let entity_id = builder.build(&world).await;
+
+    let mut schedule = Schedule::new();
+    schedule
+        .add_system(apply_velocity)
+        .add_system(double_position)
+        .add_system(stop_moving);
+    let mut stepper = schedule.step(&world).await;
+    let mut positions = Vec::new();
+    while let Some(name) = stepper.peek() {
+        assert_eq!(stepper.next().await, Some(name));
+        let entity = world.get(entity_id).await.unwrap();
+        positions.push((name, entity.get::<Position>().unwrap().0));
+    }
+    // the commands of `stop_moving` are applied once the run is finished
+    assert!(world
+        .get(entity_id)
+        .await
+        .unwrap()
+        .get::<Velocity>()
+        .is_some());
+    assert_eq!(stepper.next().await, None);
+    assert!(world
+        .get(entity_id)
+        .await
+        .unwrap()
+        .get::<Velocity>()
+        .is_none());
+    assert_eq!(
+        positions,
+        [
+            ("integration_test::apply_velocity", 2),
+            ("integration_test::double_position", 4),
+            ("integration_test::stop_moving", 4),
+        ]
+    );
+    let timings = world.get_resource::<SystemTimings>().await.unwrap();
+    assert_eq!(timings.iter().count(), 3);
+}
+
+#[derive(jest::resources::Resource)]
+struct Attempts(u32);
+
+async fn flaky(mut attempts: jest::resources::ResMut<Attempts>) -> Result<(), jest::Error> {
+    let mut attempts = attempts.get_mut().await?;
+    attempts.0 += 1;
+    Err(format!("attempt {} failed", attempts.0).into())
+}
+
+#[tokio::test]
+async fn failing_systems_follow_the_error_policy() {
+    use jest::systems::schedule::{ErrorPolicy, Schedule};
+
+    common::setup();
+
+    let world = jest::world::World::new();
+    world.insert_resource(Attempts(0)).await;
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add_bundle((Position(0), Velocity(1))).unwrap();
+    let entity_id = builder.build(&world).await;
+
+    // the failing system sits out two runs after each failure, the others go on
+    let mut schedule = Schedule::new();
+    schedule
+        .add_system(flaky)
+        .add_system(apply_velocity)
+        .set_error_handler(|system, error| {
+            assert_eq!(system, "integration_test::flaky");
+            assert!(error.to_string().ends_with("failed"));
+            ErrorPolicy::Skip(2)
+        });
+    for _ in 0..5 {
+        schedule.run(&world).await;
+    }
+    assert!(!schedule.stopped());
+    assert_eq!(world.get_resource::<Attempts>().await.unwrap().0, 2);
+    let entity = world.get(entity_id).await.unwrap();
+    assert_eq!(entity.get::<Position>().unwrap().0, 5);
+    drop(entity);
+
+    // the failing system is still skipped once, then stops the schedule, which
+    // doesn't run anything from then on
+    schedule.set_error_handler(|_, _| ErrorPolicy::Stop);
+    for _ in 0..3 {
+        schedule.run(&world).await;
+    }
+    assert!(schedule.stopped());
+    assert_eq!(world.get_resource::<Attempts>().await.unwrap().0, 3);
+    let mut stepper = schedule.step(&world).await;
+    assert_eq!(stepper.next().await, None);
+    drop(stepper);
+    let entity = world.get(entity_id).await.unwrap();
+    assert_eq!(entity.get::<Position>().unwrap().0, 7);
+}
+
+#[tokio::test]
+async fn schedule_records_system_timings() {
+    use jest::systems::schedule::{Schedule, SystemTimings};
+
+    common::setup();
+
+    let world = jest::world::World::new();
+    let mut schedule = Schedule::new();
+    schedule
+        .add_system(apply_velocity)
+        .add_system(double_position);
+    // nothing is recorded until the resource is inserted
+    schedule.run(&world).await;
+    world.insert_resource(SystemTimings::default()).await;
+    for _ in 0..3 {
+        schedule.run(&world).await;
+    }
+
+    let timings = world.get_resource::<SystemTimings>().await.unwrap();
+    let mut names: Vec<_> = timings.iter().map(|(name, _)| name).collect();
+    names.sort_unstable();
+    assert_eq!(
+        names,
+        [
+            "integration_test::apply_velocity",
+            "integration_test::double_position"
+        ]
+    );
+    let timing = timings.get("integration_test::apply_velocity").unwrap();
+    assert_eq!(timing.runs(), 3);
+    assert!(timing.min() <= timing.last() && timing.last() <= timing.max());
+}
+
+async fn compare_positions(
+    mut positions: jest::query::Query<&mut Position>,
+    mut others: jest::query::Query<&Position>,
+) {
+    let mut iter = positions.iter().await;
+    while let Some((_, position)) = iter.next().await {
+        let mut others = others.iter().await;
+        while let Some((_, other)) = others.next().await {
+            position.0 = position.0.max(other.0);
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "`integration_test::Position` is accessed mutably and more than once")]
+fn system_rejects_aliasing_params() {
+    common::setup();
+
+    let mut schedule = jest::systems::schedule::Schedule::new();
+    schedule.add_system(compare_positions);
+}
+
+async fn follow_velocities(
+    mut positions: jest::query::Query<&mut Position>,
+    mut velocities: jest::query::Query<&Velocity>,
+) {
+    let mut iter = positions.iter().await;
+    while let Some((_, position)) = iter.next().await {
+        let mut velocities = velocities.iter().await;
+        while let Some((_, velocity)) = velocities.next().await {
+            position.0 += velocity.0;
+        }
+    }
+}
+
+#[tokio::test]
+#[should_panic(expected = "locked by two queries of system")]
+async fn system_rejects_nested_queries_on_one_entity() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add_bundle((Position(0), Velocity(1))).unwrap();
+    builder.build(&world).await;
+
+    let mut schedule = jest::systems::schedule::Schedule::new();
+    schedule.add_system(follow_velocities);
+    schedule.run(&world).await;
+}
+
+static BARRIER: std::sync::OnceLock<tokio::sync::Barrier> = std::sync::OnceLock::new();
+
+async fn increment_position(mut query: jest::query::Query<&mut Position>) {
+    BARRIER.get().unwrap().wait().await;
+    let mut iter = query.iter().await;
+    while let Some((_, position)) = iter.next().await {
+        position.0 += 1;
+    }
+}
+
+async fn increment_velocity(mut query: jest::query::Query<&mut Velocity>) {
+    BARRIER.get().unwrap().wait().await;
+    let mut iter = query.iter().await;
+    while let Some((_, velocity)) = iter.next().await {
+        velocity.0 += 1;
+    }
+}
+
+#[tokio::test]
+async fn schedule_runs_disjoint_systems_concurrently() {
+    common::setup();
+
+    BARRIER.get_or_init(|| tokio::sync::Barrier::new(2));
+    let world = jest::world::World::new();
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add_bundle((Position(1), Velocity(1))).unwrap();
+    let entity_id = builder.build(&world).await;
+
+    // each system waits for the other at the barrier, so they'd deadlock if run one
+    // after the other
+    let mut schedule = jest::systems::schedule::Schedule::new();
+    schedule
+        .add_system(increment_position)
+        .add_system(increment_velocity);
+    schedule.run(&world).await;
+
+    let entity = world.get(entity_id).await.unwrap();
+    assert_eq!(entity.get::<Position>().unwrap().0, 2);
+    assert_eq!(entity.get::<Velocity>().unwrap().0, 2);
+}
+
+#[derive(jest::resources::Resource)]
+struct Score(u32);
+
+async fn add_score(mut score: jest::resources::ResMut<Score>) {
+    score.get_mut().await.unwrap().0 += 1;
+}
+
+async fn double_score(mut score: jest::resources::ResMut<Score>) {
+    score.get_mut().await.unwrap().0 *= 2;
+}
+
+#[tokio::test]
+async fn systems_share_resources() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    world.insert_resource(Score(1)).await;
+
+    let mut schedule = jest::systems::schedule::Schedule::new();
+    schedule.add_system(add_score).add_system(double_score);
+    schedule.run(&world).await;
+
+    assert_eq!(world.get_resource::<Score>().await.unwrap().0, 4);
+    assert_eq!(world.remove_resource::<Score>().await.unwrap().0, 4);
+    let err = world.get_resource_mut::<Score>().await.err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "resource `Score` isn't in the world, which registered none"
+    );
+}
+
+#[derive(jest::resources::Resource, Default)]
+#[resource(default)]
+struct HighScore(u32);
+
+#[derive(jest::resources::Resource)]
+#[resource(name = "HighScore")]
+struct OtherHighScore;
+
+#[tokio::test]
+async fn registered_resources_start_with_their_default() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    world.register_resource::<HighScore>().await;
+    world.get_resource_mut::<HighScore>().await.unwrap().0 = 3;
+    // registering again keeps the current value
+    world.register_resource::<HighScore>().await;
+    assert_eq!(world.get_resource::<HighScore>().await.unwrap().0, 3);
+    assert_eq!(world.resource_names(), ["HighScore"]);
+
+    let err = world.get_resource::<Score>().await.err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "resource `Score` isn't in the world, which registered `HighScore`"
+    );
+    let err = world.get_resource::<OtherHighScore>().await.err().unwrap();
+    assert!(matches!(
+        err,
+        jest::resources::ResourceError::WrongType {
+            name: "HighScore",
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+#[should_panic(expected = "resource name `HighScore` of")]
+async fn resource_names_are_unique() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    world.register_resource::<HighScore>().await;
+    world.register_resource::<OtherHighScore>().await;
+}
+
+async fn stop_moving(
+    mut query: jest::query::Query<&Velocity>,
+    mut commands: jest::commands::Commands,
+) {
+    let mut iter = query.iter().await;
+    while let Some((id, velocity)) = iter.next().await {
+        commands
+            .remove::<Velocity>(id)
+            .add(id, Position(velocity.0));
+    }
+}
+
+#[tokio::test]
+async fn commands_apply_after_systems() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add(Velocity(3)).unwrap();
+    let entity_id = builder.build(&world).await;
+
+    let mut schedule = jest::systems::schedule::Schedule::new();
+    schedule.add_system(stop_moving);
+    schedule.run(&world).await;
+
+    let entity = world.get(entity_id).await.unwrap();
+    assert!(entity.get::<Velocity>().is_none());
+    assert_eq!(entity.get::<Position>().unwrap().0, 3);
+}
+
+#[tokio::test]
+async fn entities_queue_their_own_despawn() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    let parent = jest::entities::builder::EntityBuilder::new()
+        .build(&world)
+        .await;
+    let child = jest::entities::builder::EntityBuilder::new()
+        .build(&world)
+        .await;
+    world
+        .get_mut(parent)
+        .await
+        .unwrap()
+        .add_child(child)
+        .await
+        .unwrap();
+
+    let mut entity = world.get_mut(child).await.unwrap();
+    entity.add(Position(0)).unwrap();
+    entity.despawn();
+    assert!(world.get(child).await.is_some());
+
+    world.apply_commands().await;
+    assert!(world.get(child).await.is_none());
+    let parent = world.get(parent).await.unwrap();
+    assert!(parent.get::<jest::hierarchy::Children>().is_none());
+}
+
+#[derive(Clone)]
+struct Inventory(Vec<&'static str>);
+
+#[derive(Debug)]
+struct NotOwned(&'static str);
+impl std::fmt::Display for NotOwned {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}` isn't in the inventory", self.0)
+    }
+}
+impl std::error::Error for NotOwned {}
+
+fn trade(
+    from: jest::entities::EntityId,
+    to: jest::entities::EntityId,
+    item: &'static str,
+) -> jest::transaction::Transaction {
+    let mut transaction = jest::transaction::Transaction::new();
+    transaction
+        .update(to, move |inventory: &mut Inventory| {
+            inventory.0.push(item);
+            Ok::<_, NotOwned>(())
+        })
+        .update(from, move |inventory: &mut Inventory| {
+            let index = inventory.0.iter().position(|other| *other == item);
+            inventory.0.remove(index.ok_or(NotOwned(item))?);
+            Ok::<_, NotOwned>(())
+        });
+    transaction
+}
+
+#[tokio::test]
+async fn transactions_apply_entirely_or_not_at_all() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    let mut players = Vec::new();
+    for items in [vec!["sword"], vec!["shield"]] {
+        let mut builder = jest::entities::builder::EntityBuilder::new();
+        builder.add(Inventory(items)).unwrap();
+        players.push(builder.build(&world).await);
+    }
+    let (alice, bob) = (players[0], players[1]);
+
+    // the second trade fails once the first is applied, and leaves no item behind
+    let mut commands = jest::commands::Commands::new(&world);
+    commands
+        .commit(trade(alice, bob, "sword"))
+        .commit(trade(alice, bob, "sword"))
+        .commit(trade(bob, alice, "shield"));
+    drop(commands);
+    world.apply_commands().await;
+    for (player, items) in [(alice, ["shield"]), (bob, ["sword"])] {
+        let player = world.get(player).await.unwrap();
+        assert_eq!(player.get::<Inventory>().unwrap().0, items);
+    }
+
+    let error = world.commit(trade(alice, bob, "sword")).await.unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "transaction rolled back: `sword` isn't in the inventory"
+    );
+    let stranger = jest::entities::builder::EntityBuilder::new()
+        .build(&world)
+        .await;
+    let error = world.commit(trade(alice, stranger, "shield")).await;
+    assert!(matches!(
+        error,
+        Err(jest::transaction::TransactionError::Missing { entity, .. }) if entity == stranger
+    ));
+    let alice = world.get(alice).await.unwrap();
+    assert_eq!(alice.get::<Inventory>().unwrap().0, ["shield"]);
+}
+
+#[derive(jest::entities::bundle::Bundle)]
+struct Moving(Position, Velocity);
+
+#[tokio::test]
+async fn derived_bundle_adds_to_existing_entity() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    let entity_id = jest::entities::builder::EntityBuilder::new()
+        .build(&world)
+        .await;
+
+    let mut entity = world.get_mut(entity_id).await.unwrap();
+    entity.add_bundle(Moving(Position(1), Velocity(2))).unwrap();
+    assert_eq!(entity.get::<Position>().unwrap().0, 1);
+    assert_eq!(entity.get::<Velocity>().unwrap().0, 2);
+    assert!(entity.add_bundle(Moving(Position(3), Velocity(4))).is_err());
+}
+
+static DESPAWNED: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+#[derive(jest::entities::component::Component)]
+#[component(on_remove = count_despawn)]
+struct Tracked;
+
+fn count_despawn(_entity: &mut jest::entities::Entity) {
+    DESPAWNED.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+}
+
+#[tokio::test]
+async fn component_hooks_run_on_despawn() {
+    common::setup();
+
+    // derived hooks run without registering the type
+    let world = jest::world::World::new();
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add(Tracked).unwrap();
+    let entity_id = builder.build(&world).await;
+
+    world.remove(entity_id).await.unwrap();
+    assert_eq!(DESPAWNED.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+    // draining an entity in the world removes its components too, but draining one
+    // that was already despawned doesn't
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add(Tracked).unwrap();
+    let entity_id = builder.build(&world).await;
+    assert_eq!(world.get_mut(entity_id).await.unwrap().drain().count(), 1);
+    assert_eq!(DESPAWNED.load(std::sync::atomic::Ordering::Relaxed), 2);
+    world
+        .get_mut(entity_id)
+        .await
+        .unwrap()
+        .add(Tracked)
+        .unwrap();
+    let entity = world.remove(entity_id).await.unwrap();
+    let _ = jest::entities::builder::EntityBuilder::from(entity);
+    assert_eq!(DESPAWNED.load(std::sync::atomic::Ordering::Relaxed), 3);
+}
+
+#[tokio::test]
+async fn components_survive_archetype_moves() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    let mut ids = Vec::new();
+    for i in 0..200 {
+        let mut builder = jest::entities::builder::EntityBuilder::new();
+        builder.add(Position(i)).unwrap();
+        ids.push(builder.build(&world).await);
+    }
+    for (i, id) in ids.iter().enumerate() {
+        let mut entity = world.get_mut(*id).await.unwrap();
+        if i % 3 == 0 {
+            entity.add(Velocity(i as u32)).unwrap();
+        }
+        if i % 5 == 0 {
+            assert_eq!(entity.remove::<Position>().unwrap().0, i as u32);
+        }
+    }
+
+    let mut query = world.query::<(&Position, &Velocity)>();
+    let mut iter = query.iter().await;
+    let mut visited = 0;
+    while let Some((_, (position, velocity))) = iter.next().await {
+        assert_eq!(position.0, velocity.0);
+        visited += 1;
+    }
+    drop(iter);
+    // multiples of 3 that aren't multiples of 5
+    assert_eq!(visited, 67 - 14);
+
+    let entity = world.remove(ids[3]).await.unwrap();
+    assert_eq!(entity.get::<Velocity>().unwrap().0, 3);
+    let moved = jest::entities::builder::EntityBuilder::from(entity)
+        .build(&world)
+        .await;
+    assert_eq!(
+        world.get(moved).await.unwrap().get::<Position>().unwrap().0,
+        3
+    );
+}
+
+#[derive(jest::entities::component::Component)]
+#[component(storage = "sparse")]
+struct Stunned(u32);
+
+#[tokio::test]
+async fn sparse_components_come_and_go() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    let mut ids = Vec::new();
+    for i in 0..10 {
+        let mut builder = jest::entities::builder::EntityBuilder::new();
+        builder.add(Position(i)).unwrap();
+        builder.add(Stunned(i)).unwrap();
+        ids.push(builder.build(&world).await);
+    }
+    for (i, id) in ids.iter().enumerate() {
+        let mut entity = world.get_mut(*id).await.unwrap();
+        if i % 2 == 0 {
+            assert_eq!(entity.remove::<Stunned>().unwrap().0, i as u32);
+        } else {
+            entity.add(Velocity(i as u32)).unwrap();
+        }
+    }
+    world.remove(ids[1]).await.unwrap();
+
+    let mut query = world.query::<(&Position, &Stunned)>();
+    let mut iter = query.iter().await;
+    let mut visited = 0;
+    while let Some((_, (position, stunned))) = iter.next().await {
+        assert_eq!(position.0, stunned.0);
+        visited += 1;
+    }
+    drop(iter);
+    assert_eq!(visited, 4);
+
+    let entity = world.get(ids[3]).await.unwrap();
+    assert_eq!(entity.get::<Velocity>().unwrap().0, 3);
+    assert_eq!(entity.get::<Stunned>().unwrap().0, 3);
+}
+
+#[derive(jest::entities::component::Component)]
+#[component(storage = "sparse")]
+struct Slowed<T: Send + Sync + 'static>(T);
+
+#[tokio::test]
+#[should_panic(expected = "can't change its storage type")]
+async fn storage_type_is_fixed_once_used() {
+    common::setup();
+
+    // generic types aren't registered up front, so this is stored in a table
+    let world = jest::world::World::new();
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add(Slowed(0u32)).unwrap();
+    builder.build(&world).await;
+    world.register_component::<Slowed<u32>>();
+}
+
+static MANA_ADDED: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+// implemented by hand, as a plugin loaded after startup would register it
+struct Mana(u32);
+impl jest::entities::component::ComponentOptions for Mana {
+    fn on_add(_entity: &mut jest::entities::Entity) {
+        MANA_ADDED.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+#[tokio::test]
+async fn components_register_into_a_live_world() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add(Mana(1)).unwrap();
+    let existing = builder.build(&world).await;
+    assert!(!world.component_names().contains(&"integration_test::Mana"));
+    assert_eq!(MANA_ADDED.load(std::sync::atomic::Ordering::Relaxed), 0);
+
+    world.register_component::<Mana>();
+    assert!(world.component_names().contains(&"integration_test::Mana"));
+    // derived options are registered from the start
+    assert!(world
+        .component_names()
+        .contains(&"integration_test::Tracked"));
+    assert_eq!(
+        world.get(existing).await.unwrap().get::<Mana>().unwrap().0,
+        1
+    );
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add(Mana(2)).unwrap();
+    builder.build(&world).await;
+    assert_eq!(MANA_ADDED.load(std::sync::atomic::Ordering::Relaxed), 1);
+}
+
+#[tokio::test]
+async fn get_or_spawn_uses_the_given_id() {
+    use jest::entities::EntityId;
+
+    common::setup();
+
+    let world = jest::world::World::new();
+    let id = EntityId::from_raw(3, 1).unwrap();
+    world
+        .get_or_spawn(id)
+        .await
+        .unwrap()
+        .add(Position(3))
+        .unwrap();
+    // the same entity is returned once it exists
+    world
+        .get_or_spawn(id)
+        .await
+        .unwrap()
+        .add(Velocity(1))
+        .unwrap();
+    let mut query = world.query::<(&Position, &Velocity)>();
+    let mut iter = query.iter().await;
+    assert_eq!(iter.next().await.map(|(found, _)| found), Some(id));
+    assert!(iter.next().await.is_none());
+    drop(iter);
+
+    // the slots before it are used by later entities
+    let first = jest::entities::builder::EntityBuilder::new()
+        .build(&world)
+        .await;
+    let second = jest::entities::builder::EntityBuilder::new()
+        .build(&world)
+        .await;
+    assert_eq!(
+        [first.index(), second.index()].map(|index| index < 3),
+        [true; 2]
+    );
+    assert!(world
+        .get_or_spawn(EntityId::from_raw(first.index(), 3).unwrap())
+        .await
+        .is_none());
+
+    world.remove(id).await.unwrap();
+    assert!(world.get_or_spawn(id).await.is_none());
+
+    // an ID far past every other doesn't create the slots before it
+    let far = EntityId::from_raw(100_000_000, 1).unwrap();
+    world
+        .get_or_spawn(far)
+        .await
+        .unwrap()
+        .add(Position(4))
+        .unwrap();
+    let mut query = world.query::<&Position>();
+    let mut iter = query.iter().await;
+    assert_eq!(iter.next().await.map(|(found, _)| found), Some(far));
+    assert!(iter.next().await.is_none());
+    drop(iter);
+    world.remove(far).await.unwrap();
+    assert!(world.get_or_spawn(far).await.is_none());
+}
+
+async fn draw_once(mut rng: jest::resources::ResMut<jest::rng::Rng>) {
+    rng.get_mut().await.unwrap().next_u64();
+}
+
+#[tokio::test]
+async fn rng_is_reseeded_every_run() {
+    use jest::rng::Rng;
+
+    common::setup();
+
+    let mut draws = Vec::new();
+    for extra_draws in [0, 5] {
+        let world = jest::world::World::new();
+        world.insert_resource(Rng::new(7)).await;
+        let mut schedule = jest::systems::schedule::Schedule::new();
+        schedule.add_system(draw_once);
+        schedule.run(&world).await;
+        // drawing more during one run doesn't change the numbers of the next
+        for _ in 0..extra_draws {
+            world.get_resource_mut::<Rng>().await.unwrap().next_u64();
+        }
+        schedule.run(&world).await;
+        let mut rng = world.get_resource_mut::<Rng>().await.unwrap();
+        assert_eq!(rng.tick(), 2);
+        draws.push((rng.next_u64(), rng.fork(3).next_u64()));
+    }
+    assert_eq!(draws[0], draws[1]);
+    let mut rng = Rng::new(7);
+    assert_ne!(rng.fork(3).next_u64(), rng.fork(4).next_u64());
+    assert!((0..1000).all(|_| (10..13).contains(&rng.range(10..13))));
+}
+
+#[derive(jest::resources::Resource)]
+struct ChangedPositions(u32);
+
+async fn move_all(mut query: jest::query::Query<(&mut Position, &Velocity)>) {
+    let mut iter = query.iter().await;
+    while let Some((_, (position, velocity))) = iter.next().await {
+        position.0 += velocity.0;
+    }
+}
+
+async fn count_changed(
+    mut query: jest::query::Query<&Position, jest::query::Changed<Position>>,
+    mut changed: jest::resources::ResMut<ChangedPositions>,
+) {
+    let mut iter = query.iter().await;
+    while iter.next().await.is_some() {
+        changed.get_mut().await.unwrap().0 += 1;
+    }
+}
+
+#[tokio::test]
+async fn changed_filter_sees_changes_since_last_run() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    world.insert_resource(ChangedPositions(0)).await;
+    for i in 0..3 {
+        let mut builder = jest::entities::builder::EntityBuilder::new();
+        builder.add(Position(0)).unwrap();
+        if i > 0 {
+            builder.add(Velocity(i)).unwrap();
+        }
+        builder.build(&world).await;
+    }
+
+    let mut schedule = jest::systems::schedule::Schedule::new();
+    schedule.add_system(move_all);
+    schedule.add_system(count_changed);
+    // every position was added, then two of them moved
+    schedule.run(&world).await;
+    assert_eq!(world.get_resource::<ChangedPositions>().await.unwrap().0, 3);
+    schedule.run(&world).await;
+    assert_eq!(world.get_resource::<ChangedPositions>().await.unwrap().0, 5);
+
+    let mut query = world.query::<jest::entities::fetch::Ref<Position>>();
+    let mut iter = query.iter().await;
+    let mut changed = 0;
+    while let Some((_, position)) = iter.next().await {
+        assert!(!position.is_added());
+        if position.is_changed() {
+            assert_ne!(position.0, 0);
+            changed += 1;
+        }
+    }
+    assert_eq!(changed, 2);
+}
+
+async fn bump_positions(mut query: jest::query::Query<&mut Position>) {
+    // lets a reader running at the same time go first
+    tokio::task::yield_now().await;
+    let mut iter = query.iter().await;
+    while let Some((_, position)) = iter.next().await {
+        position.0 += 1;
+    }
+}
+
+async fn count_moving(
+    mut query: jest::query::Query<&Velocity, jest::query::Changed<Position>>,
+    mut changed: jest::resources::ResMut<ChangedPositions>,
+) {
+    let mut iter = query.iter().await;
+    while iter.next().await.is_some() {
+        changed.get_mut().await.unwrap().0 += 1;
+    }
+}
+
+#[tokio::test]
+async fn changed_filter_waits_for_writers() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    world.insert_resource(ChangedPositions(0)).await;
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add_bundle((Position(0), Velocity(1))).unwrap();
+    builder.build(&world).await;
+
+    // the filter reads `Position`, so the reader runs after the writer every time
+    let mut schedule = jest::systems::schedule::Schedule::new();
+    schedule.add_system(bump_positions).add_system(count_moving);
+    for _ in 0..6 {
+        schedule.run(&world).await;
+    }
+    assert_eq!(world.get_resource::<ChangedPositions>().await.unwrap().0, 6);
+}
+
+async fn reset_changed(
+    mut query: jest::query::Query<&mut Position, jest::query::Changed<Position>>,
+    mut changed: jest::resources::ResMut<ChangedPositions>,
+) {
+    let mut iter = query.iter().await;
+    while let Some((_, position)) = iter.next().await {
+        position.0 = 0;
+        changed.get_mut().await.unwrap().0 += 1;
+    }
+}
+
+#[tokio::test]
+async fn changed_filter_ignores_own_writes() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    world.insert_resource(ChangedPositions(0)).await;
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add(Position(1)).unwrap();
+    let entity_id = builder.build(&world).await;
+
+    let mut schedule = jest::systems::schedule::Schedule::new();
+    schedule.add_system(reset_changed);
+    for _ in 0..5 {
+        schedule.run(&world).await;
+    }
+    // only the insertion counts, the system's own writes don't
+    assert_eq!(world.get_resource::<ChangedPositions>().await.unwrap().0, 1);
+
+    world
+        .get_mut(entity_id)
+        .await
+        .unwrap()
+        .get_mut::<Position>()
+        .unwrap()
+        .0 = 2;
+    schedule.run(&world).await;
+    assert_eq!(world.get_resource::<ChangedPositions>().await.unwrap().0, 2);
+}
+
+#[derive(jest::resources::Resource)]
+struct RemovedVelocities(Vec<jest::entities::EntityId>);
+
+async fn stop_all(
+    mut query: jest::query::Query<&Velocity>,
+    mut commands: jest::commands::Commands,
+) {
+    let mut iter = query.iter().await;
+    while let Some((id, velocity)) = iter.next().await {
+        if velocity.0 > 0 {
+            commands.remove::<Velocity>(id);
+        }
+    }
+}
+
+async fn collect_removed(
+    removed: jest::removal::RemovedComponents<Velocity>,
+    mut collected: jest::resources::ResMut<RemovedVelocities>,
+) {
+    for id in removed.iter() {
+        collected.get_mut().await.unwrap().0.push(id);
+    }
+}
+
+#[tokio::test]
+async fn removed_components_are_seen_once() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    world.insert_resource(RemovedVelocities(Vec::new())).await;
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add(Velocity(1)).unwrap();
+    let entity_id = builder.build(&world).await;
+
+    let mut schedule = jest::systems::schedule::Schedule::new();
+    schedule.add_system(stop_all);
+    schedule.add_system(collect_removed);
+    // the removal is deferred until the end of the first run
+    for _ in 0..3 {
+        schedule.run(&world).await;
+    }
+    let collected = world.get_resource::<RemovedVelocities>().await.unwrap();
+    assert_eq!(collected.0, [entity_id]);
+    assert_eq!(world.removed::<Velocity>().count(), 0);
+}
+
+struct Collision(u32);
+#[derive(jest::resources::Resource)]
+struct Collisions(Vec<u32>);
+
+async fn read_collisions(
+    mut collisions: jest::events::EventReader<Collision>,
+    mut seen: jest::resources::ResMut<Collisions>,
+) {
+    let collisions = collisions.read().await;
+    let mut seen = seen.get_mut().await.unwrap();
+    seen.0
+        .extend(collisions.iter().map(|collision| collision.0));
+}
+
+async fn send_collision(mut collisions: jest::events::EventWriter<Collision>) {
+    collisions.send(Collision(7)).await;
+}
+
+#[tokio::test]
+async fn events_reach_earlier_readers_and_expire() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    world.insert_resource(Collisions(Vec::new())).await;
+    let mut schedule = jest::systems::schedule::Schedule::new();
+    // the reader runs first, so it sees each event during the next run
+    schedule.add_system(read_collisions);
+    schedule.add_system(send_collision);
+    schedule.run(&world).await;
+    assert!(world
+        .get_resource::<Collisions>()
+        .await
+        .unwrap()
+        .0
+        .is_empty());
+    schedule.run(&world).await;
+    assert_eq!(world.get_resource::<Collisions>().await.unwrap().0, [7]);
+
+    // events are dropped two updates after they were sent
+    let mut schedule = jest::systems::schedule::Schedule::new();
+    schedule.run(&world).await;
+    let events = world
+        .get_resource::<jest::events::Events<Collision>>()
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    drop(events);
+    schedule.run(&world).await;
+    let events = world
+        .get_resource::<jest::events::Events<Collision>>()
+        .await
+        .unwrap();
+    assert!(events.is_empty());
+}
+
+#[derive(jest::resources::Resource)]
+struct Transitions(Vec<String>);
+
+async fn log_transitions(
+    mut exited: jest::events::EventReader<jest::fsm::StateExited<&'static str>>,
+    mut entered: jest::events::EventReader<jest::fsm::StateEntered<&'static str>>,
+    mut log: jest::resources::ResMut<Transitions>,
+) {
+    let mut log = log.get_mut().await.unwrap();
+    let exited = exited.read().await;
+    log.0
+        .extend(exited.iter().map(|event| format!("exit {}", event.state)));
+    let entered = entered.read().await;
+    log.0
+        .extend(entered.iter().map(|event| format!("enter {}", event.state)));
+}
+
+#[tokio::test]
+async fn state_machines_send_enter_and_exit_events() {
+    use jest::fsm::StateMachine;
+
+    common::setup();
+
+    let world = jest::world::World::new();
+    world.insert_resource(Transitions(Vec::new())).await;
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder
+        .add(StateMachine::new("idle").with_guard(|_, to| *to != "dead"))
+        .unwrap();
+    let npc = builder.build(&world).await;
+
+    let mut schedule = jest::systems::schedule::Schedule::new();
+    schedule
+        .add_system(jest::fsm::update_state_machines::<&'static str>)
+        .add_system(log_transitions);
+    for next in ["walking", "walking", "dead"] {
+        let mut entity = world.get_mut(npc).await.unwrap();
+        let machine = entity.get_mut::<StateMachine<&str>>().unwrap();
+        machine.transition(next);
+        assert_eq!(machine.next(), Some(&next));
+        drop(entity);
+        schedule.run(&world).await;
+    }
+
+    // the second request was to the current state, and the guard rejected the third
+    let entity = world.get(npc).await.unwrap();
+    let machine = entity.get::<StateMachine<&str>>().unwrap();
+    assert_eq!((*machine.state(), machine.next()), ("walking", None));
+    drop(entity);
+    let log = world.get_resource::<Transitions>().await.unwrap();
+    assert_eq!(log.0, ["exit idle", "enter walking"]);
+}
+
+#[tokio::test]
+async fn hierarchy_stays_consistent() {
+    use jest::hierarchy::{Children, HierarchyError, Parent};
+
+    common::setup();
+
+    let world = jest::world::World::new();
+    let mut ids = Vec::new();
+    for _ in 0..4 {
+        ids.push(
+            jest::entities::builder::EntityBuilder::new()
+                .build(&world)
+                .await,
+        );
+    }
+    let (a, b, c, d) = (ids[0], ids[1], ids[2], ids[3]);
+    world.get_mut(a).await.unwrap().add_child(b).await.unwrap();
+    world.get_mut(b).await.unwrap().add_child(c).await.unwrap();
+    assert!(matches!(
+        world.get_mut(c).await.unwrap().add_child(a).await,
+        Err(HierarchyError::Cycle(_))
+    ));
+
+    // reparenting detaches from the previous parent
+    world.get_mut(d).await.unwrap().add_child(c).await.unwrap();
+    assert!(world.get(b).await.unwrap().get::<Children>().is_none());
+    assert_eq!(
+        world.get(c).await.unwrap().get::<Parent>().unwrap().get(),
+        d
+    );
+
+    // removing a parent orphans its children
+    world.remove(d).await.unwrap();
+    assert!(world.get(c).await.unwrap().get::<Parent>().is_none());
+
+    // the hierarchy can't be taken apart by hand
+    let mut entity = world.get_mut(a).await.unwrap();
+    assert!(entity.remove::<Children>().is_none());
+    assert_eq!(entity.drain().count(), 0);
+    assert_eq!(&**entity.get::<Children>().unwrap(), [b]);
+    drop(entity);
+    let mut commands = jest::commands::Commands::new(&world);
+    commands.remove::<Parent>(b);
+    drop(commands);
+    world.apply_commands().await;
+    assert_eq!(
+        world.get(b).await.unwrap().get::<Parent>().unwrap().get(),
+        a
+    );
+
+    let mut commands = jest::commands::Commands::new(&world);
+    commands.add_child(b, c).despawn_recursive(a);
+    drop(commands);
+    world.apply_commands().await;
+    for id in [a, b, c] {
+        assert!(world.get(id).await.is_none());
+    }
+}
+
+struct OwnedBy(jest::entities::EntityId);
+impl jest::relations::Relation for OwnedBy {
+    fn target(&self) -> jest::entities::EntityId {
+        self.0
+    }
+}
+
+#[tokio::test]
+async fn relations_are_indexed_both_ways() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    world.register_relation::<OwnedBy>();
+    let alice = jest::entities::builder::EntityBuilder::new()
+        .build(&world)
+        .await;
+    let bob = jest::entities::builder::EntityBuilder::new()
+        .build(&world)
+        .await;
+    let mut items = Vec::new();
+    for _ in 0..3 {
+        let mut builder = jest::entities::builder::EntityBuilder::new();
+        builder.add(OwnedBy(alice)).unwrap();
+        items.push(builder.build(&world).await);
+    }
+    assert_eq!(world.relations::<OwnedBy>(alice), items);
+
+    // retargeting and despawning a source update the index
+    let mut item = world.get_mut(items[0]).await.unwrap();
+    assert_eq!(item.replace(OwnedBy(bob)).unwrap().0, alice);
+    drop(item);
+    world.remove(items[1]).await.unwrap();
+    assert_eq!(world.relations::<OwnedBy>(alice), [items[2]]);
+    assert_eq!(world.relations::<OwnedBy>(bob), [items[0]]);
+
+    // despawning the target removes the relation from its sources
+    world.remove(bob).await.unwrap();
+    assert!(world
+        .get(items[0])
+        .await
+        .unwrap()
+        .get::<OwnedBy>()
+        .is_none());
+    assert!(world.relations::<OwnedBy>(bob).is_empty());
+}
+
+const ALLIES: jest::layers::Layers = jest::layers::Layers::layer(0);
+const ENEMIES: jest::layers::Layers = jest::layers::Layers::layer(1);
+const FLYING: jest::layers::Layers = jest::layers::Layers::layer(2);
+
+struct Hits(u32);
+
+async fn shoot_flying_enemies(query: jest::query::Query<&mut Hits>) {
+    let mut query = query.in_layers(ENEMIES).in_layers(FLYING);
+    let mut iter = query.iter().await;
+    while let Some((_, hits)) = iter.next().await {
+        hits.0 += 1;
+    }
+}
+
+#[tokio::test]
+async fn queries_can_be_restricted_to_layers() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    let mut targets = Vec::new();
+    for layers in [
+        Some(ENEMIES | FLYING),
+        Some(ENEMIES),
+        Some(ALLIES | FLYING),
+        Some(jest::layers::Layers::ALL),
+        None,
+    ] {
+        let mut builder = jest::entities::builder::EntityBuilder::new();
+        builder.add(Hits(0)).unwrap();
+        if let Some(layers) = layers {
+            builder.add(layers).unwrap();
+        }
+        targets.push(builder.build(&world).await);
+    }
+
+    let mut schedule = jest::systems::schedule::Schedule::new();
+    schedule.add_system(shoot_flying_enemies);
+    schedule.run(&world).await;
+    let mut hits = Vec::new();
+    for id in targets {
+        hits.push(world.get(id).await.unwrap().get::<Hits>().unwrap().0);
+    }
+    assert_eq!(hits, [1, 0, 0, 1, 0]);
+}
+
+struct Cell(i32, i32);
+impl jest::indexes::Indexed for Cell {
+    type Key = (i32, i32);
+
+    fn key(&self) -> (i32, i32) {
+        (self.0, self.1)
+    }
+}
+
+async fn step_right(mut query: jest::query::Query<&mut Cell>) {
+    let mut iter = query.iter().await;
+    while let Some((_, cell)) = iter.next().await {
+        cell.0 += 1;
+    }
+}
+
+async fn sorted_lookup(
+    world: &jest::world::World,
+    key: (i32, i32),
+) -> Vec<jest::entities::EntityId> {
+    let mut entities = world.lookup::<Cell>(&key).await;
+    entities.sort_unstable();
+    entities
+}
+
+#[tokio::test]
+async fn indexes_follow_changed_components() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    world.register_index::<Cell>();
+    let mut units = Vec::new();
+    for x in [0, 0, 1] {
+        let mut builder = jest::entities::builder::EntityBuilder::new();
+        builder.add(Cell(x, 0)).unwrap();
+        units.push(builder.build(&world).await);
+    }
+    assert_eq!(sorted_lookup(&world, (0, 0)).await, units[..2]);
+    assert_eq!(sorted_lookup(&world, (1, 0)).await, units[2..]);
+
+    // changes made through queries are picked up by the next lookup
+    let mut schedule = jest::systems::schedule::Schedule::new();
+    schedule.add_system(step_right);
+    schedule.run(&world).await;
+    assert!(sorted_lookup(&world, (0, 0)).await.is_empty());
+    assert_eq!(sorted_lookup(&world, (1, 0)).await, units[..2]);
+    assert_eq!(sorted_lookup(&world, (2, 0)).await, units[2..]);
+
+    // so are removed components and despawned entities
+    let mut unit = world.get_mut(units[0]).await.unwrap();
+    unit.get_mut::<Cell>().unwrap().1 = 5;
+    unit.remove::<Cell>().unwrap();
+    drop(unit);
+    world.remove(units[1]).await.unwrap();
+    assert!(sorted_lookup(&world, (1, 0)).await.is_empty());
+    assert!(sorted_lookup(&world, (1, 5)).await.is_empty());
+    assert_eq!(sorted_lookup(&world, (2, 0)).await, units[2..]);
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for OwnedBy {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for OwnedBy {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        jest::entities::EntityId::deserialize(deserializer).map(OwnedBy)
+    }
+}
+
+#[cfg(feature = "serde")]
+#[tokio::test]
+async fn saved_worlds_keep_their_ids() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    world.register_relation::<OwnedBy>();
+    world.register_serializable::<OwnedBy>();
+    // reuse the first slot, so that the owner's generation isn't the initial one
+    let temporary = jest::entities::builder::EntityBuilder::new()
+        .build(&world)
+        .await;
+    world.remove(temporary).await.unwrap();
+    let owner = jest::entities::builder::EntityBuilder::new()
+        .build(&world)
+        .await;
+    assert_ne!(owner.generation(), temporary.generation());
+    let mut builder = jest::entities::builder::EntityBuilder::new();
+    builder.add(OwnedBy(owner)).unwrap();
+    builder.add(Position(7)).unwrap();
+    let item = builder.build(&world).await;
+    let json = world.save(serde_json::value::Serializer).await.unwrap();
+
+    let restored = jest::world::World::new();
+    restored.register_relation::<OwnedBy>();
+    restored.register_serializable::<OwnedBy>();
+    let stale = jest::entities::builder::EntityBuilder::new()
+        .build(&restored)
+        .await;
+    restored.load(json.clone()).await.unwrap();
+    assert_eq!(
+        restored
+            .get(item)
+            .await
+            .unwrap()
+            .get::<OwnedBy>()
+            .unwrap()
+            .0,
+        owner
+    );
+    // unregistered components aren't saved
+    assert!(restored
+        .get(item)
+        .await
+        .unwrap()
+        .get::<Position>()
+        .is_none());
+    assert_eq!(restored.relations::<OwnedBy>(owner), [item]);
+    assert_eq!(stale.index(), owner.index());
+    assert!(restored.get(stale).await.is_none());
+
+    let entity = restored.get(item).await.unwrap();
+    let saved = serde_json::to_value(&*entity).unwrap();
+    drop(entity);
+    let copy = restored.deserialize_entity(saved).unwrap();
+    assert_eq!(copy.get::<OwnedBy>().unwrap().0, owner);
+
+    // loading fails without touching the world if a component type is unknown
+    let other = jest::world::World::new();
+    let survivor = jest::entities::builder::EntityBuilder::new()
+        .build(&other)
+        .await;
+    assert!(other.load(json).await.is_err());
+    assert!(other.get(survivor).await.is_some());
+    assert!(other
+        .load(serde_json::json!([{ "id": "0:1", "components": {} }]))
+        .await
+        .is_err());
+
+    // IDs are restored directly, however often their slot was reused before saving
+    let reused: jest::entities::EntityId = "3:4294967295".parse().unwrap();
+    other
+        .load(serde_json::json!([{ "id": reused, "components": {} }]))
+        .await
+        .unwrap();
+    assert!(other.get(reused).await.is_some());
+    assert!(other.get(survivor).await.is_none());
+
+    // as are IDs far past every other, without creating the slots before them
+    let far: jest::entities::EntityId = "100000000:1".parse().unwrap();
+    other
+        .load(serde_json::json!([
+            { "id": "2:1", "components": {} },
+            { "id": far, "components": {} },
+        ]))
+        .await
+        .unwrap();
+    assert!(other.get(far).await.is_some());
+    let spawned = jest::entities::builder::EntityBuilder::new()
+        .build(&other)
+        .await;
+    assert_eq!(spawned.index(), 1);
+}
+
+#[cfg(feature = "serde")]
+#[tokio::test]
+async fn saved_worlds_keep_their_hierarchy() {
+    use jest::hierarchy::{Children, Parent};
+
+    common::setup();
+
+    let world = jest::world::World::new();
+    let parent = jest::entities::builder::EntityBuilder::new()
+        .build(&world)
+        .await;
+    let child = jest::entities::builder::EntityBuilder::new()
+        .build(&world)
+        .await;
+    world
+        .get_mut(parent)
+        .await
+        .unwrap()
+        .add_child(child)
+        .await
+        .unwrap();
+    let json = world.save(serde_json::value::Serializer).await.unwrap();
+
+    let restored = jest::world::World::new();
+    restored.load(json).await.unwrap();
+    assert_eq!(
+        &**restored
+            .get(parent)
+            .await
+            .unwrap()
+            .get::<Children>()
+            .unwrap(),
+        [child]
+    );
+    assert_eq!(
+        restored
+            .get(child)
+            .await
+            .unwrap()
+            .get::<Parent>()
+            .unwrap()
+            .get(),
+        parent
+    );
+    assert_eq!(restored.despawn_recursive(parent).await.len(), 2);
+
+    // links that don't match are rejected, whether in a world or on their own
+    let mut json = world.save(serde_json::value::Serializer).await.unwrap();
+    json.as_array_mut().unwrap().push(serde_json::json!({
+        "id": "9:1",
+        "components": { "jest::hierarchy::Parent": parent },
+    }));
+    assert!(restored.load(json).await.is_err());
+    assert!(restored.get(parent).await.is_none());
+    assert!(restored
+        .deserialize_entity(serde_json::json!({ "jest::hierarchy::Parent": parent }))
+        .is_err());
+}
+
+#[cfg(feature = "serde")]
+#[tokio::test]
+#[should_panic(expected = "is part of the hierarchy and can't be added by hand")]
+async fn hierarchy_components_cant_be_added_by_hand() {
+    common::setup();
+
+    let world = jest::world::World::new();
+    let parent = jest::entities::builder::EntityBuilder::new()
+        .build(&world)
+        .await;
+    let other = jest::entities::builder::EntityBuilder::new()
+        .build(&world)
+        .await;
+    let link: jest::hierarchy::Parent = serde_json::from_value(serde_json::json!(parent)).unwrap();
+    let _ = world.get_mut(other).await.unwrap().add(link);
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Position {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Position {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u32::deserialize(deserializer).map(Position)
+    }
+}
+
+#[cfg(feature = "serde")]
+struct Tags(Vec<(u8, Option<char>)>);
+#[cfg(feature = "serde")]
+impl serde::Serialize for Tags {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Tags {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Tags)
+    }
+}
+
+#[cfg(feature = "serde")]
+#[tokio::test]
+async fn scenes_spawn_nested_prefabs() {
+    use jest::scene::{Scene, SceneError};
+
+    common::setup();
+
+    let world = jest::world::World::new();
+    world.register_serializable::<Position>();
+    world.register_serializable::<Tags>();
+    let scene = Scene::from_ron(
+        r#"
+        /* a tank and its turret, both built on the same base */
+        (
+            prefabs: {
+                "base": (components: { "Position": 1, "Tags": [(1, Some('b')), (2, None)] }),
+                "turret": Entity(prefab: "base", components: { "Position": 2 }),
+                "tank": (prefab: "base", children: [(prefab: "turret")]),
+            },
+            entities: [(prefab: "tank"), (components: { "Tags": [] })],
+        )
+    "#,
+    )
+    .unwrap();
+    let roots = scene.spawn(&world).await.unwrap();
+    assert_eq!(roots.len(), 2);
+
+    let tank = world.get(roots[0]).await.unwrap();
+    assert_eq!(tank.get::<Position>().unwrap().0, 1);
+    assert_eq!(tank.get::<Tags>().unwrap().0, [(1, Some('b')), (2, None)]);
+    let turret = tank.get::<jest::hierarchy::Children>().unwrap()[0];
+    drop(tank);
+    let turret = world.get(turret).await.unwrap();
+    assert_eq!(turret.get::<Position>().unwrap().0, 2);
+    assert_eq!(turret.get::<Tags>().unwrap().0.len(), 2);
+    drop(turret);
+    let other = world.get(roots[1]).await.unwrap();
+    assert!(other.get::<Tags>().unwrap().0.is_empty());
+    assert!(other.get::<Position>().is_none());
+    drop(other);
+
+    // a scene can be spawned again
+    scene.spawn(&world).await.unwrap();
+    assert_eq!(
+        world
+            .find(|entity| entity.get::<Tags>().is_some())
+            .await
+            .len(),
+        6
+    );
+
+    assert!(matches!(
+        Scene::from_ron(r#"(entities: [(prefab: "missing")])"#),
+        Err(SceneError::UnknownPrefab(name)) if name == "missing"
+    ));
+    assert!(matches!(
+        Scene::from_ron(r#"(prefabs: { "box": (children: [(prefab: "box")]) })"#),
+        Err(SceneError::PrefabCycle(_))
+    ));
+    assert!(matches!(
+        Scene::from_ron("(entities: [)"),
+        Err(SceneError::Parse {
+            line: 1,
+            column: 13,
+            ..
+        })
+    ));
+
+    // components are only checked when spawning, and nothing is spawned on failure
+    let scene = Scene::from_ron("(entities: [\n    (components: { \"Tags\": [] }),\n    (components: { \"Position\": -1 }),\n])").unwrap();
+    assert!(matches!(
+        scene.spawn(&world).await,
+        Err(SceneError::Parse { line: 3, .. })
+    ));
+    let scene = Scene::from_ron(r#"(entities: [(components: { "Velocity": 1 })])"#).unwrap();
+    assert!(matches!(
+        scene.spawn(&world).await,
+        Err(SceneError::UnknownComponent(_))
+    ));
+    let scene = Scene::from_ron(&format!(
+        r#"(entities: [(components: {{ "Parent": "{}" }})])"#,
+        roots[0]
+    ))
+    .unwrap();
+    assert!(matches!(
+        scene.spawn(&world).await,
+        Err(SceneError::HierarchyComponent(_))
+    ));
+    let scene = Scene::from_ron(
+        r#"(entities: [(components: { "Position": 1, "integration_test::Position": 2 })])"#,
+    )
+    .unwrap();
+    assert!(matches!(
+        scene.spawn(&world).await,
+        Err(SceneError::DuplicateComponent { first, second })
+            if first == "Position" && second == "integration_test::Position"
+    ));
+    assert_eq!(
+        world
+            .find(|entity| entity.get::<Tags>().is_some())
+            .await
+            .len(),
+        6
+    );
+}